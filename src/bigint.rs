@@ -0,0 +1,350 @@
+// Exact integers past 2^53 for the `bignum` feature, `Object::BigInt`.
+//
+// Numbers stay `f64` as long as integer results are exact. When `+`, `-` or
+// `*` on two whole numbers would round, the result is computed here instead,
+// and a result that fits back into 2^53 turns into a plain number again.
+// Anything with a fraction, and `/`, keeps using floats. The cost is an extra
+// check on every integer `+ - *` and an allocation per big value, digits are
+// stored as base 10^9 limbs and multiplied the schoolbook way
+use crate::lexer::TokenType;
+use crate::object::Object;
+use std::{cmp::Ordering, fmt};
+
+const BASE: u64 = 1_000_000_000;
+// Largest magnitude up to which every integer is an exact f64
+const MAX_EXACT: f64 = 9_007_199_254_740_992.0;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    // Least significant limb first, no trailing zeros, empty for 0
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    fn from_u64(mut n: u64) -> BigInt {
+        let mut limbs = vec![];
+        while n > 0 {
+            limbs.push((n % BASE) as u32);
+            n /= BASE;
+        }
+        BigInt {
+            negative: false,
+            limbs,
+        }
+    }
+
+    // Exact value of a whole f64, None for fractions, NaN and the infinities
+    pub fn from_f64(n: f64) -> Option<BigInt> {
+        if !n.is_finite() || n.fract() != 0.0 {
+            return None;
+        }
+        // n is mantissa * 2^exp with a 53 bit mantissa
+        let bits = n.abs().to_bits();
+        let exp = ((bits >> 52) & 0x7ff) as i64;
+        let mut mantissa = bits & ((1 << 52) - 1);
+        if exp != 0 {
+            mantissa |= 1 << 52;
+        }
+        let mut shift = exp.max(1) - 1075;
+        if shift < 0 {
+            mantissa = mantissa.checked_shr(-shift as u32).unwrap_or(0);
+            shift = 0;
+        }
+        let mut value = BigInt::from_u64(mantissa);
+        while shift > 0 {
+            let step = shift.min(29);
+            value.mul_small(1 << step);
+            shift -= step;
+        }
+        value.negative = n < 0.0 && !value.limbs.is_empty();
+        Some(value)
+    }
+
+    // Decimal digits with an optional leading `-`
+    pub fn parse(digits: &str) -> Option<BigInt> {
+        let (negative, digits) = match digits.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, digits),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let bytes = digits.as_bytes();
+        let mut limbs = vec![];
+        let mut end = bytes.len();
+        while end > 0 {
+            let start = end.saturating_sub(9);
+            let chunk = std::str::from_utf8(&bytes[start..end]).ok()?;
+            limbs.push(chunk.parse().ok()?);
+            end = start;
+        }
+        let mut value = BigInt { negative, limbs };
+        value.normalize();
+        Some(value)
+    }
+
+    // Nearest f64, going through the digits so it's rounded only once
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    // Whether the value is within 2^53, where every integer is an exact f64
+    pub fn fits_f64(&self) -> bool {
+        self.cmp_magnitude(&BigInt::from_u64(MAX_EXACT as u64)) != Ordering::Greater
+    }
+
+    // A number when the value is exact as an f64, a big integer otherwise
+    pub fn into_object(self) -> Object {
+        if self.fits_f64() {
+            Object::Number(self.to_f64())
+        } else {
+            Object::BigInt(Box::new(self))
+        }
+    }
+
+    fn normalize(&mut self) {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        if self.limbs.is_empty() {
+            self.negative = false;
+        }
+    }
+
+    fn mul_small(&mut self, factor: u32) {
+        let mut carry = 0;
+        for limb in &mut self.limbs {
+            let product = *limb as u64 * factor as u64 + carry;
+            *limb = (product % BASE) as u32;
+            carry = product / BASE;
+        }
+        while carry > 0 {
+            self.limbs.push((carry % BASE) as u32);
+            carry /= BASE;
+        }
+    }
+
+    // Quotient rounded towards zero
+    pub fn div_small(&self, divisor: u64) -> BigInt {
+        let mut limbs = vec![0; self.limbs.len()];
+        let mut rem: u128 = 0;
+        for (i, &limb) in self.limbs.iter().enumerate().rev() {
+            let current = rem * BASE as u128 + limb as u128;
+            limbs[i] = (current / divisor as u128) as u32;
+            rem = current % divisor as u128;
+        }
+        let mut result = BigInt {
+            negative: self.negative,
+            limbs,
+        };
+        result.normalize();
+        result
+    }
+
+    fn cmp_magnitude(&self, other: &BigInt) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+
+    fn add_magnitude(&self, other: &BigInt) -> Vec<u32> {
+        let mut limbs = Vec::with_capacity(self.limbs.len().max(other.limbs.len()) + 1);
+        let mut carry = 0;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            let sum = *self.limbs.get(i).unwrap_or(&0) as u64
+                + *other.limbs.get(i).unwrap_or(&0) as u64
+                + carry;
+            limbs.push((sum % BASE) as u32);
+            carry = sum / BASE;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+        limbs
+    }
+
+    // |self| - |other|, which has to be the smaller one
+    fn sub_magnitude(&self, other: &BigInt) -> Vec<u32> {
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0;
+        for (i, &limb) in self.limbs.iter().enumerate() {
+            let subtrahend = *other.limbs.get(i).unwrap_or(&0) as i64 + borrow;
+            let mut diff = limb as i64 - subtrahend;
+            borrow = 0;
+            if diff < 0 {
+                diff += BASE as i64;
+                borrow = 1;
+            }
+            limbs.push(diff as u32);
+        }
+        limbs
+    }
+
+    pub fn plus(&self, other: &BigInt) -> BigInt {
+        let mut result = if self.negative == other.negative {
+            BigInt {
+                negative: self.negative,
+                limbs: self.add_magnitude(other),
+            }
+        } else if self.cmp_magnitude(other) == Ordering::Less {
+            BigInt {
+                negative: other.negative,
+                limbs: other.sub_magnitude(self),
+            }
+        } else {
+            BigInt {
+                negative: self.negative,
+                limbs: self.sub_magnitude(other),
+            }
+        };
+        result.normalize();
+        result
+    }
+
+    pub fn negate(&self) -> BigInt {
+        BigInt {
+            negative: !self.negative && !self.limbs.is_empty(),
+            limbs: self.limbs.clone(),
+        }
+    }
+
+    pub fn minus(&self, other: &BigInt) -> BigInt {
+        self.plus(&other.negate())
+    }
+
+    pub fn times(&self, other: &BigInt) -> BigInt {
+        let mut wide = vec![0u64; self.limbs.len() + other.limbs.len()];
+        for (i, &l) in self.limbs.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &r) in other.limbs.iter().enumerate() {
+                let product = wide[i + j] + l as u64 * r as u64 + carry;
+                wide[i + j] = product % BASE;
+                carry = product / BASE;
+            }
+            let mut k = i + other.limbs.len();
+            while carry > 0 {
+                let sum = wide[k] + carry;
+                wide[k] = sum % BASE;
+                carry = sum / BASE;
+                k += 1;
+            }
+        }
+        let mut result = BigInt {
+            negative: self.negative != other.negative,
+            limbs: wide.into_iter().map(|limb| limb as u32).collect(),
+        };
+        result.normalize();
+        result
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => self.cmp_magnitude(other),
+            (true, true) => other.cmp_magnitude(self),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((last, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{last}")?;
+        for limb in rest.iter().rev() {
+            write!(f, "{limb:09}")?;
+        }
+        Ok(())
+    }
+}
+
+// The operand as an exact integer, if it is one
+fn as_bigint(obj: &Object) -> Option<BigInt> {
+    match obj {
+        Object::BigInt(n) => Some((**n).clone()),
+        Object::Number(n) => BigInt::from_f64(*n),
+        _ => None,
+    }
+}
+
+// `+ - * /` where the f64 result could be wrong, None leaves the operation to
+// the usual number rules
+pub fn bin_op(left: &Object, op: &TokenType, right: &Object) -> Option<Object> {
+    let has_big = matches!(left, Object::BigInt(_)) || matches!(right, Object::BigInt(_));
+    if let (Object::Number(l), Object::Number(r)) = (left, right) {
+        let exact = |n: f64| n.abs() <= MAX_EXACT;
+        let result = match op {
+            TokenType::Plus => l + r,
+            TokenType::Minus => l - r,
+            TokenType::Multiply => l * r,
+            _ => return None,
+        };
+        // A result of exactly 2^53 may have been rounded from 2^53 + 1
+        if exact(*l) && exact(*r) && result.abs() < MAX_EXACT {
+            return None;
+        }
+    } else if !has_big {
+        return None;
+    }
+
+    let (l, r) = match (as_bigint(left), as_bigint(right)) {
+        (Some(l), Some(r)) => (l, r),
+        // A fraction on either side, big integers become floats
+        _ => {
+            let l = to_f64(left)?;
+            let r = to_f64(right)?;
+            return Some(Object::Number(match op {
+                TokenType::Plus => l + r,
+                TokenType::Minus => l - r,
+                TokenType::Multiply => l * r,
+                TokenType::Divide => l / r,
+                _ => return None,
+            }));
+        }
+    };
+    let result = match op {
+        TokenType::Plus => l.plus(&r),
+        TokenType::Minus => l.minus(&r),
+        TokenType::Multiply => l.times(&r),
+        TokenType::Divide if r.limbs.is_empty() => {
+            return Some(Object::error(format!("Division by zero in {l} / {r}")));
+        }
+        TokenType::Divide => return Some(Object::Number(l.to_f64() / r.to_f64())),
+        _ => return None,
+    };
+    Some(result.into_object())
+}
+
+fn to_f64(obj: &Object) -> Option<f64> {
+    match obj {
+        Object::BigInt(n) => Some(n.to_f64()),
+        Object::Number(n) => Some(*n),
+        _ => None,
+    }
+}
+
+// Order of two values when at least one is a big integer
+pub fn compare(left: &Object, right: &Object) -> Option<Ordering> {
+    if !matches!(left, Object::BigInt(_)) && !matches!(right, Object::BigInt(_)) {
+        return None;
+    }
+    match (as_bigint(left), as_bigint(right)) {
+        (Some(l), Some(r)) => Some(l.cmp(&r)),
+        _ => to_f64(left)?.partial_cmp(&to_f64(right)?),
+    }
+}
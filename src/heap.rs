@@ -0,0 +1,143 @@
+// Priority queue behind `Object::Heap`, made by the `heap()` native.
+//
+// Items are kept ordered by priority in a BTreeMap so both ends can be popped
+// in O(log n), which lets one structure serve as a min-heap (`pop_min`) and a
+// max-heap (`pop_max`). Items with the same priority come out in the order
+// they were pushed
+use crate::object::Object;
+use std::{cmp::Ordering, collections::BTreeMap};
+
+#[derive(Clone, Debug, Default)]
+pub struct Heap {
+    items: BTreeMap<Key, Object>,
+    next_seq: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Key {
+    priority: f64,
+    seq: u64,
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        self.priority
+            .total_cmp(&other.priority)
+            .then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key {}
+
+impl Heap {
+    pub fn push(&mut self, value: Object, priority: f64) {
+        let key = Key {
+            priority,
+            seq: self.next_seq,
+        };
+        self.next_seq += 1;
+        self.items.insert(key, value);
+    }
+
+    pub fn pop_min(&mut self) -> Option<Object> {
+        self.items.pop_first().map(|(_, value)| value)
+    }
+
+    pub fn pop_max(&mut self) -> Option<Object> {
+        let key = *self.first_of_max()?.0;
+        self.items.remove(&key)
+    }
+
+    pub fn peek_min(&self) -> Option<&Object> {
+        self.items.first_key_value().map(|(_, value)| value)
+    }
+
+    pub fn peek_max(&self) -> Option<&Object> {
+        self.first_of_max().map(|(_, value)| value)
+    }
+
+    // The earliest pushed item with the highest priority
+    fn first_of_max(&self) -> Option<(&Key, &Object)> {
+        let priority = self.items.last_key_value()?.0.priority;
+        self.items.range(Key { priority, seq: 0 }..).next()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    // Equality with `same` comparing the values, so `Object` can bound how
+    // deep it looks into them
+    pub fn eq_by(&self, other: &Heap, mut same: impl FnMut(&Object, &Object) -> bool) -> bool {
+        self.len() == other.len()
+            && self
+                .items
+                .iter()
+                .zip(&other.items)
+                .all(|((l_key, l), (r_key, r))| l_key.priority == r_key.priority && same(l, r))
+    }
+
+    // Values from the lowest priority to the highest
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &Object> {
+        self.items.values()
+    }
+}
+
+// Two heaps are equal when they hold the same values at the same priorities in
+// the same order, no matter when they were pushed
+impl PartialEq for Heap {
+    fn eq(&self, other: &Heap) -> bool {
+        self.eq_by(other, |l, r| l == r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interpreter;
+
+    fn text(s: &str) -> Object {
+        Object::String(Box::new(s.to_string()))
+    }
+
+    #[test]
+    fn pops_by_priority_with_ties_in_push_order() {
+        let mut heap = Heap::default();
+        heap.push(text("b"), 2.0);
+        heap.push(text("a"), 1.0);
+        heap.push(text("c"), 3.0);
+        heap.push(text("c2"), 3.0);
+        assert_eq!(heap.peek_min(), Some(&text("a")));
+        assert_eq!(heap.peek_max(), Some(&text("c")));
+        assert_eq!(heap.pop_max(), Some(text("c")));
+        assert_eq!(heap.pop_max(), Some(text("c2")));
+        assert_eq!(heap.pop_min(), Some(text("a")));
+        assert_eq!(heap.len(), 1);
+        assert_eq!(heap.pop_min(), Some(text("b")));
+        assert_eq!(heap.pop_min(), None);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn heap_methods_run_from_scripts() {
+        let src = "let h = heap()\nh.push(\"far\", 9)\nh.push(\"near\", 1)\nh.pop_min()";
+        let result = Interpreter::new(".".to_string(), None).eval_str(src);
+        assert_eq!(result, text("near"));
+    }
+}
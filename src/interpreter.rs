@@ -1,17 +1,104 @@
-use crate::std_native;
-use crate::{lexer::Lexer, lexer::TokenType, object::Object, parser::Parser, parser::Tree};
+use crate::std_native::{self, NativeFn};
+use crate::{
+    heap::Heap,
+    json,
+    lexer::Lexer,
+    lexer::TokenType,
+    logger::Logger,
+    object::{default_step, range_items, to_int, Object},
+    parser::Parser,
+    parser::Tree,
+};
 use core::iter::Iterator;
-use rustc_hash::FxHashMap;
-use std::{env, fs::File, io::Read, path::Path};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::cmp::Ordering;
+use std::{
+    env,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 // default dir name for std libs
 const STD_DIR: &str = "std";
+// default for `Interpreter::max_call_depth`
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+// Longest string and list `*` builds, a typo like `"a" * 1e18` fails instead of
+// aborting on a capacity overflow
+const MAX_REPEAT_LEN: usize = 1 << 28;
+
+// Source of the ids in `Interpreter::scope_ids`, shared by every interpreter
+// so a closure from an imported file never matches a scope of the importer
+static NEXT_SCOPE_ID: AtomicU64 = AtomicU64::new(0);
+
+// Set by `Interpreter::interrupt`, loops stop with an error while it is set
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// The first error among already evaluated operands, they are checked before use
+fn first_error<'a>(objects: impl IntoIterator<Item = &'a Object>) -> Option<Object> {
+    objects
+        .into_iter()
+        .find(|obj| matches!(obj, Object::Error { .. }))
+        .cloned()
+}
+
+// Stable merge sort stopping at the first error from `cmp`. Unlike
+// `slice::sort_by` it can't panic when a user comparator isn't a total order
+fn merge_sort(
+    mut items: Vec<Object>,
+    cmp: &mut dyn FnMut(&Object, &Object) -> Result<Ordering, Object>,
+) -> Result<Vec<Object>, Object> {
+    if items.len() < 2 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, cmp)?;
+    let right = merge_sort(right, cmp)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        if cmp(l, r)? == Ordering::Greater {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
 
 #[derive(Debug)]
 pub struct Interpreter {
     scopes: Vec<FxHashMap<String, Object>>,
+    // Unique id of each scope in `scopes`, increasing from the global one
+    scope_ids: Vec<u64>,
     current_path: String,
     std_path: String,
+    call_depth: usize,
+    // Calls nested deeper than this fail with a runtime error instead of
+    // overflowing the Rust stack. Each call takes a few native frames, so the
+    // thread running the interpreter needs a big enough stack for the limit
+    pub max_call_depth: usize,
+    // Callbacks registered with `after`, waiting for `run_timers`
+    timers: Vec<(Instant, Object)>,
+    // State of the generator behind `random`
+    rng_state: u64,
+    // Epoch of `clock`
+    started: Instant,
+    // Index into `std_native::LOG_LEVELS` of the least severe level `log` prints
+    pub log_level: usize,
+    // Namespaces of the files imported so far by canonical path, shared with
+    // the interpreters running imported files so each file runs once
+    modules: FxHashMap<String, FxHashMap<String, Object>>,
+    // Files whose imports are still running, outermost first. Importing one of
+    // them again is a cycle
+    loading: Vec<String>,
 }
 
 impl Interpreter {
@@ -37,6 +124,275 @@ impl Interpreter {
                 function: std_native::native_write,
             },
         );
+        base_scope.insert(
+            "writeln".to_string(),
+            Object::NativeFn {
+                name: "writeln".to_string(),
+                function: std_native::native_writeln,
+            },
+        );
+        base_scope.insert(
+            "format".to_string(),
+            Object::NativeFn {
+                name: "format".to_string(),
+                function: std_native::native_format,
+            },
+        );
+        base_scope.insert(
+            "is_prime".to_string(),
+            Object::NativeFn {
+                name: "is_prime".to_string(),
+                function: std_native::native_is_prime,
+            },
+        );
+        base_scope.insert(
+            "factorize".to_string(),
+            Object::NativeFn {
+                name: "factorize".to_string(),
+                function: std_native::native_factorize,
+            },
+        );
+        base_scope.insert(
+            "input".to_string(),
+            Object::NativeFn {
+                name: "input".to_string(),
+                function: std_native::native_input,
+            },
+        );
+        base_scope.insert(
+            "read_file".to_string(),
+            Object::NativeFn {
+                name: "read_file".to_string(),
+                function: std_native::native_read_file,
+            },
+        );
+        base_scope.insert(
+            "write_file".to_string(),
+            Object::NativeFn {
+                name: "write_file".to_string(),
+                function: std_native::native_write_file,
+            },
+        );
+        base_scope.insert(
+            "help".to_string(),
+            Object::NativeFn {
+                name: "help".to_string(),
+                function: std_native::native_help,
+            },
+        );
+        base_scope.insert(
+            "type".to_string(),
+            Object::NativeFn {
+                name: "type".to_string(),
+                function: std_native::native_type,
+            },
+        );
+        base_scope.insert(
+            "describe".to_string(),
+            Object::NativeFn {
+                name: "describe".to_string(),
+                function: std_native::native_describe,
+            },
+        );
+        base_scope.insert(
+            "mem_report".to_string(),
+            Object::NativeFn {
+                name: "mem_report".to_string(),
+                function: std_native::native_mem_report,
+            },
+        );
+        base_scope.insert(
+            "try_number".to_string(),
+            Object::NativeFn {
+                name: "try_number".to_string(),
+                function: std_native::native_try_number,
+            },
+        );
+        base_scope.insert(
+            "try_int".to_string(),
+            Object::NativeFn {
+                name: "try_int".to_string(),
+                function: std_native::native_try_int,
+            },
+        );
+        base_scope.insert(
+            "try_bool".to_string(),
+            Object::NativeFn {
+                name: "try_bool".to_string(),
+                function: std_native::native_try_bool,
+            },
+        );
+        base_scope.insert(
+            "currency".to_string(),
+            Object::NativeFn {
+                name: "currency".to_string(),
+                function: std_native::native_currency,
+            },
+        );
+        base_scope.insert(
+            "percent".to_string(),
+            Object::NativeFn {
+                name: "percent".to_string(),
+                function: std_native::native_percent,
+            },
+        );
+        base_scope.insert(
+            "date_now".to_string(),
+            Object::NativeFn {
+                name: "date_now".to_string(),
+                function: std_native::native_date_now,
+            },
+        );
+        base_scope.insert(
+            "format_date".to_string(),
+            Object::NativeFn {
+                name: "format_date".to_string(),
+                function: std_native::native_format_date,
+            },
+        );
+        base_scope.insert(
+            "after".to_string(),
+            Object::NativeFn {
+                name: "after".to_string(),
+                function: std_native::native_after,
+            },
+        );
+        base_scope.insert(
+            "run_timers".to_string(),
+            Object::NativeFn {
+                name: "run_timers".to_string(),
+                function: std_native::native_run_timers,
+            },
+        );
+        base_scope.insert(
+            "make".to_string(),
+            Object::NativeFn {
+                name: "make".to_string(),
+                function: std_native::native_make,
+            },
+        );
+        base_scope.insert(
+            "json_stringify".to_string(),
+            Object::NativeFn {
+                name: "json_stringify".to_string(),
+                function: std_native::native_json_stringify,
+            },
+        );
+        base_scope.insert(
+            "json_parse".to_string(),
+            Object::NativeFn {
+                name: "json_parse".to_string(),
+                function: std_native::native_json_parse,
+            },
+        );
+        base_scope.insert(
+            "deep_equal".to_string(),
+            Object::NativeFn {
+                name: "deep_equal".to_string(),
+                function: std_native::native_deep_equal,
+            },
+        );
+        base_scope.insert(
+            "approx".to_string(),
+            Object::NativeFn {
+                name: "approx".to_string(),
+                function: std_native::native_approx,
+            },
+        );
+        base_scope.insert("math".to_string(), std_native::math_namespace());
+        base_scope.insert("log".to_string(), std_native::log_namespace());
+        base_scope.insert("matrix".to_string(), std_native::matrix_namespace());
+        base_scope.insert(
+            "random".to_string(),
+            Object::NativeFn {
+                name: "random".to_string(),
+                function: std_native::native_random,
+            },
+        );
+        base_scope.insert(
+            "random_int".to_string(),
+            Object::NativeFn {
+                name: "random_int".to_string(),
+                function: std_native::native_random_int,
+            },
+        );
+        base_scope.insert(
+            "freeze".to_string(),
+            Object::NativeFn {
+                name: "freeze".to_string(),
+                function: std_native::native_freeze,
+            },
+        );
+        base_scope.insert(
+            "clock".to_string(),
+            Object::NativeFn {
+                name: "clock".to_string(),
+                function: std_native::native_clock,
+            },
+        );
+        base_scope.insert(
+            "sleep".to_string(),
+            Object::NativeFn {
+                name: "sleep".to_string(),
+                function: std_native::native_sleep,
+            },
+        );
+        base_scope.insert(
+            "to_number".to_string(),
+            Object::NativeFn {
+                name: "to_number".to_string(),
+                function: std_native::native_to_number,
+            },
+        );
+        base_scope.insert(
+            "to_string".to_string(),
+            Object::NativeFn {
+                name: "to_string".to_string(),
+                function: std_native::native_to_string,
+            },
+        );
+        base_scope.insert(
+            "ref".to_string(),
+            Object::NativeFn {
+                name: "ref".to_string(),
+                function: std_native::native_ref,
+            },
+        );
+        base_scope.insert(
+            "deref".to_string(),
+            Object::NativeFn {
+                name: "deref".to_string(),
+                function: std_native::native_deref,
+            },
+        );
+        base_scope.insert(
+            "set".to_string(),
+            Object::NativeFn {
+                name: "set".to_string(),
+                function: std_native::native_set,
+            },
+        );
+        base_scope.insert(
+            "heap".to_string(),
+            Object::NativeFn {
+                name: "heap".to_string(),
+                function: std_native::native_heap,
+            },
+        );
+        base_scope.insert(
+            "assert_throws".to_string(),
+            Object::NativeFn {
+                name: "assert_throws".to_string(),
+                function: std_native::native_assert_throws,
+            },
+        );
+        base_scope.insert(
+            "from_entries".to_string(),
+            Object::NativeFn {
+                name: "from_entries".to_string(),
+                function: std_native::native_from_entries,
+            },
+        );
         base_scope.insert(
             "exit".to_string(),
             Object::NativeFn {
@@ -55,17 +411,31 @@ impl Interpreter {
 
         Self {
             scopes: vec![base_scope],
+            scope_ids: vec![NEXT_SCOPE_ID.fetch_add(1, AtomicOrdering::Relaxed)],
             current_path,
             std_path,
+            call_depth: 0,
+            max_call_depth: MAX_CALL_DEPTH,
+            timers: vec![],
+            rng_state: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64),
+            started: Instant::now(),
+            log_level: 1,
+            modules: FxHashMap::default(),
+            loading: vec![],
         }
     }
 
     fn enter_scope(&mut self) {
         self.scopes.push(FxHashMap::default());
+        self.scope_ids
+            .push(NEXT_SCOPE_ID.fetch_add(1, AtomicOrdering::Relaxed));
     }
 
     fn exit_scope(&mut self) {
         self.scopes.pop();
+        self.scope_ids.pop();
     }
 
     fn set_var(&mut self, name: &str, value: Object) -> &mut Object {
@@ -84,10 +454,181 @@ impl Interpreter {
         None
     }
 
+    // Copies the variables `body` uses that live outside the global scope
+    // with the id of their scope, inner scopes win
+    fn capture_locals(&self, body: &[Tree]) -> FxHashMap<String, (u64, Object)> {
+        let mut names = FxHashSet::default();
+        for tree in body {
+            tree.collect_names(&mut names);
+        }
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let (id, scope) = self.scope_ids[1..]
+                    .iter()
+                    .zip(&self.scopes[1..])
+                    .rev()
+                    .find(|(_, scope)| scope.contains_key(&name))?;
+                let value = scope[&name].clone();
+                Some((name, (*id, value)))
+            })
+            .collect()
+    }
+
+    // The scope with `id` if it is still running
+    fn live_scope(&mut self, id: u64) -> Option<&mut FxHashMap<String, Object>> {
+        let index = self.scope_ids.binary_search(&id).ok()?;
+        self.scopes.get_mut(index)
+    }
+
+    // Resolves a script path relative to the directory of the running file
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        Path::new(&self.current_path).join(path)
+    }
+
+    // Exposes a host function to scripts. It goes into the global scope, so
+    // it is visible everywhere and replaces a builtin with the same name
+    pub fn register_native(&mut self, name: &str, function: NativeFn) {
+        let native = Object::NativeFn {
+            name: name.to_string(),
+            function,
+        };
+        self.scopes[0].insert(name.to_string(), native);
+    }
+
+    // Lexes, parses and runs `src`, returning the last result. Stops at the
+    // first `Object::Error` and returns it
+    pub fn eval_str(&mut self, src: &str) -> Object {
+        let mut lexer = Lexer::new(src);
+        let mut parser = Parser::with_source(lexer.tokenize(), src);
+
+        let mut result = Object::Null;
+        for stmt in parser.parse_tokens() {
+            result = self.interpret(&stmt);
+            if let Object::Error { .. } = result {
+                break;
+            }
+        }
+        result
+    }
+
+    // Asks the running evaluation to stop at the next loop iteration. Only
+    // stores a flag, so it is safe to call from a signal handler
+    pub fn interrupt() {
+        INTERRUPTED.store(true, AtomicOrdering::Relaxed);
+    }
+
+    pub fn clear_interrupt() {
+        INTERRUPTED.store(false, AtomicOrdering::Relaxed);
+    }
+
+    fn interrupted() -> Option<Object> {
+        INTERRUPTED
+            .load(AtomicOrdering::Relaxed)
+            .then(|| Object::error("Interrupted"))
+    }
+
+    // Reports an error raised while running a script
+    pub fn runtime_error(&self, msg: &str) {
+        Logger::runtime_error(msg, None);
+    }
+
+    // Reports an `Object::Error` that reached the top level
+    pub fn report_error(&self, err: &Object) {
+        if let Object::Error { msg, loc } = err {
+            Logger::runtime_error(msg, *loc);
+        }
+    }
+
+    // Scope depth, number of variables and the objects they hold
+    pub fn mem_usage(&self) -> (usize, usize, usize) {
+        let variables = self.scopes.iter().map(|scope| scope.len()).sum();
+        let objects = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.values())
+            .map(Object::count_objects)
+            .sum();
+        (self.scopes.len(), variables, objects)
+    }
+
+    // Marks the script being run as loading, so a file it imports that
+    // imports it back is reported as a cycle instead of running it twice
+    pub fn set_entry_file(&mut self, file: &Path) {
+        if let Ok(canonical) = file.canonicalize() {
+            self.loading = vec![canonical.to_string_lossy().to_string()];
+        }
+    }
+
+    // Returns false if the delay is too long to represent
+    pub fn add_timer(&mut self, delay: Duration, callback: Object) -> bool {
+        match Instant::now().checked_add(delay) {
+            Some(at) => {
+                self.timers.push((at, callback));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Monotonic seconds since the interpreter was created
+    pub fn elapsed_secs(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    // splitmix64, fast and good enough for scripts but not for anything secure
+    pub fn random_u64(&mut self) -> u64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform float in [0, 1)
+    pub fn random_f64(&mut self) -> f64 {
+        (self.random_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Removes the timers that are due, earliest first
+    pub fn take_due_timers(&mut self) -> Vec<(Instant, Object)> {
+        let now = Instant::now();
+        let (mut due, pending): (Vec<_>, Vec<_>) =
+            self.timers.drain(..).partition(|(at, _)| *at <= now);
+        self.timers = pending;
+        due.sort_by_key(|(at, _)| *at);
+        due
+    }
+
+    // Puts back due timers that didn't get to run
+    pub fn restore_timers(&mut self, timers: impl IntoIterator<Item = (Instant, Object)>) {
+        self.timers.extend(timers);
+    }
+
+    pub fn pending_timers(&self) -> usize {
+        self.timers.len()
+    }
+
+    // Names visible from the current scope, used for REPL completion
+    pub fn scope_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.keys().cloned())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
     fn bin_op(&self, left: Object, op: &TokenType, right: Object) -> Object {
-        use Object::{Invalid, List, Null, Number, String};
+        use Object::{Invalid, List, Null, Number, Range, String};
         use TokenType::*;
 
+        #[cfg(feature = "bignum")]
+        if let Some(result) = crate::bigint::bin_op(&left, op, &right) {
+            return result;
+        }
         match op {
             Plus => match (left, right) {
                 (Number(l), Number(r)) => Number(l + r),
@@ -108,13 +649,22 @@ impl Interpreter {
                     l.push(r);
                     List(l)
                 }
+                // Shifts both bounds, `(1..5) + 2` is `3..7`
+                (Range(start, end, step), Number(n)) | (Number(n), Range(start, end, step)) => {
+                    Range(start + n, end + n, step)
+                }
+                (Range(..), Range(..)) => Object::error("Can't add two ranges"),
                 _ => Null,
             },
             Minus => match (left, right) {
                 (Number(l), Number(r)) => Number(l - r),
+                (Range(start, end, step), Number(n)) => Range(start - n, end - n, step),
 
                 (Object::String(mut s), Object::Number(n)) => {
-                    let n = n as usize;
+                    let n = match to_int(n) {
+                        Some(n) => n.max(0) as usize,
+                        None => return Null,
+                    };
                     // count total chars
                     let total = s.chars().count();
                     if n >= total {
@@ -137,24 +687,58 @@ impl Interpreter {
             },
             Multiply => match (left, right) {
                 (Number(l), Number(r)) => Number(l * r),
-                (Number(l), String(r)) | (String(r), Number(l)) => {
-                    String(Box::new(r.repeat(l as usize)))
-                }
-                (List(ref l), Number(r)) => List(
-                    l.iter()
-                        .cycle()
-                        .take(l.len() * r as usize)
-                        .cloned()
-                        .collect(),
-                ),
+                // Scales the bounds and the step, `(1..5) * 2` is `2..10 step 2`
+                // with the same number of items. The step is truncated like a
+                // written one, so it can't be scaled down to 0
+                (Range(start, end, step), Number(n)) | (Number(n), Range(start, end, step)) => {
+                    let step = (step * n).trunc();
+                    if step == 0.0 || !step.is_finite() {
+                        return Object::error(format!(
+                            "Can't scale a range by {n}, its step would be 0"
+                        ));
+                    }
+                    Range(start * n, end * n, step)
+                }
+                (Number(l), String(r)) | (String(r), Number(l)) => match to_int(l) {
+                    Some(l) => match r.len().checked_mul(l.max(0) as usize) {
+                        Some(len) if len <= MAX_REPEAT_LEN => {
+                            String(Box::new(r.repeat(l.max(0) as usize)))
+                        }
+                        _ => Object::error(format!(
+                            "Repeating a string {l} times is longer than {MAX_REPEAT_LEN} bytes"
+                        )),
+                    },
+                    None => Null,
+                },
+                (List(ref l), Number(r)) => match to_int(r) {
+                    Some(r) => match l.len().checked_mul(r.max(0) as usize) {
+                        Some(len) if len <= MAX_REPEAT_LEN => {
+                            List(l.iter().cycle().take(len).cloned().collect())
+                        }
+                        _ => Object::error(format!(
+                            "Repeating a list {r} times is longer than {MAX_REPEAT_LEN} items"
+                        )),
+                    },
+                    None => Null,
+                },
                 _ => Null,
             },
+            // Always float division, `7 / 2` is 3.5. Whole results print without
+            // a fraction so `10 / 2` shows 5, `math.floor_div` rounds down
             Divide => match (left, right) {
-                (Number(l), Number(r)) if r != 0.0 => Number(l / r),
-                _ => Invalid,
+                (Number(l), Number(r)) if r == 0.0 => {
+                    Object::error(format!("Division by zero in {l} / {r}"))
+                }
+                (Number(l), Number(r)) => Number(l / r),
+                (l, r) => Object::error(format!(
+                    "Can't divide {} by {}",
+                    l.type_name(),
+                    r.type_name()
+                )),
             },
             BitAnd => left & right,
             BitOR => left | right,
+            BitXor => left ^ right,
             Shl => left << right,
             Shr => left >> right,
             _ => Invalid,
@@ -162,41 +746,47 @@ impl Interpreter {
     }
 
     fn cmp_op(&self, left: Object, op: &TokenType, right: Object) -> Object {
-        use Object::{Bool, Number};
-
+        use Object::{Bool, Number, String};
+
+        #[cfg(feature = "bignum")]
+        if let Some(ordering) = crate::bigint::compare(&left, &right) {
+            match op {
+                TokenType::Greater => return Bool(ordering.is_gt()),
+                TokenType::GreatEqu => return Bool(ordering.is_ge()),
+                TokenType::Less => return Bool(ordering.is_lt()),
+                TokenType::LessEqu => return Bool(ordering.is_le()),
+                _ => {}
+            }
+        }
         match op {
             // Direct equality and inequality checks
-            TokenType::EquEqu => return Bool(left == right),
-            TokenType::NotEqu => return Bool(left != right),
-
-            // Lazy evaluation for greater/less comparison
-            TokenType::Greater => {
-                if let (Number(left_num), Number(right_num)) = (left, right) {
-                    return Bool(left_num > right_num);
-                }
-                Bool(false)
-            }
-            TokenType::GreatEqu => {
-                if let (Number(left_num), Number(right_num)) = (left, right) {
-                    return Bool(left_num >= right_num);
-                }
-                Bool(false)
-            }
-            TokenType::Less => {
-                if let (Number(left_num), Number(right_num)) = (left, right) {
-                    return Bool(left_num < right_num);
-                }
-                Bool(false)
-            }
-            TokenType::LessEqu => {
-                if let (Number(left_num), Number(right_num)) = (left, right) {
-                    return Bool(left_num <= right_num);
-                }
-                Bool(false)
-            }
+            TokenType::EquEqu => Bool(left == right),
+            TokenType::NotEqu => Bool(left != right),
+
+            // Numbers compare numerically, strings lexicographically
+            TokenType::Greater => match (left, right) {
+                (Number(l), Number(r)) => Bool(l > r),
+                (String(l), String(r)) => Bool(l > r),
+                _ => Bool(false),
+            },
+            TokenType::GreatEqu => match (left, right) {
+                (Number(l), Number(r)) => Bool(l >= r),
+                (String(l), String(r)) => Bool(l >= r),
+                _ => Bool(false),
+            },
+            TokenType::Less => match (left, right) {
+                (Number(l), Number(r)) => Bool(l < r),
+                (String(l), String(r)) => Bool(l < r),
+                _ => Bool(false),
+            },
+            TokenType::LessEqu => match (left, right) {
+                (Number(l), Number(r)) => Bool(l <= r),
+                (String(l), String(r)) => Bool(l <= r),
+                _ => Bool(false),
+            },
 
             // Logical NOT, AND, OR operations
-            TokenType::Bang => return Bool(!left),
+            TokenType::Bang => Bool(!left),
             TokenType::And => {
                 Bool(left.to_bool_obj().get_bool_value() && right.to_bool_obj().get_bool_value())
             }
@@ -210,50 +800,193 @@ impl Interpreter {
     }
 
     pub fn interpret(&mut self, tree: &Tree) -> Object {
+        let mut result = self.interpret_tree(tree);
+        // The innermost node that knows its location claims an error raised
+        // below it, e.g. a native failing inside a call
+        if let Object::Error {
+            loc: loc @ None, ..
+        } = &mut result
+        {
+            *loc = tree.loc();
+        }
+        result
+    }
+
+    fn interpret_tree(&mut self, tree: &Tree) -> Object {
         match tree {
             Tree::Empty() => Object::Null,
             Tree::Number(num) => Object::Number(*num),
+            #[cfg(feature = "bignum")]
+            Tree::BigInt(n) => Object::BigInt(Box::new(n.clone())),
             Tree::Bool(b) => Object::Bool(*b),
             Tree::String(s) => Object::String(s.clone()),
+            Tree::InterpString(parts) => {
+                let mut string = String::new();
+                for part in parts {
+                    match self.interpret(part) {
+                        err @ Object::Error { .. } => return err,
+                        value => string += &value.to_string(),
+                    }
+                }
+                Object::String(Box::new(string))
+            }
             Tree::List(list) => {
                 let mut buf = vec![];
-                list.iter().for_each(|item| {
-                    buf.push(self.interpret(item));
-                });
+                for item in list {
+                    match self.interpret(item) {
+                        err @ Object::Error { .. } => return err,
+                        value => buf.push(value),
+                    }
+                }
                 Object::List(buf)
             }
-            Tree::Ident(var) => self.get_var(&var).unwrap_or(&mut Object::Null).clone(),
-            Tree::Range(start, end) => {
+            Tree::Map(entries) => {
+                let mut map = FxHashMap::default();
+                for (key, value) in entries {
+                    match self.interpret(value) {
+                        err @ Object::Error { .. } => return err,
+                        value => {
+                            map.insert(key.to_string(), value);
+                        }
+                    }
+                }
+                Object::Map(map)
+            }
+            Tree::Ident(var, _) => self
+                .get_var(var)
+                .map_or(Object::Null, |obj| obj.clone().unfrozen()),
+            Tree::Range(start, end, step) => {
                 let start_obj = self.interpret(start);
                 let end_obj = self.interpret(end);
-                if let (Object::Number(s), Object::Number(e)) = (start_obj, end_obj) {
-                    return Object::Range(s, e);
+                let step_obj = match (step, &start_obj, &end_obj) {
+                    (Some(step), ..) => self.interpret(step),
+                    (None, Object::Number(s), Object::Number(e)) => {
+                        Object::Number(default_step(*s, *e))
+                    }
+                    (None, ..) => Object::Number(1.0),
+                };
+                if let Some(err) = first_error([&start_obj, &end_obj, &step_obj]) {
+                    return err;
                 }
-                Object::Invalid
-            }
-            Tree::ListCall(var, index) => self
-                .interpret(var)
-                .get_list_index(self.interpret(index).to_number_obj().get_number_value() as usize),
-            Tree::Ret(expr) => Object::Ret(Box::new(self.interpret(expr))),
-            Tree::BinOp(left, op, right) => {
+                let step = match step_obj {
+                    Object::Number(step) if step.trunc() == 0.0 => {
+                        return Object::error("Range step can't be 0")
+                    }
+                    Object::Number(step) => step.trunc(),
+                    other => {
+                        return Object::error(format!(
+                            "Range step has to be a number found {}",
+                            other.type_name()
+                        ))
+                    }
+                };
+                if let (Object::Number(s), Object::Number(e)) = (start_obj, end_obj) {
+                    return Object::Range(s, e, step);
+                }
+                Object::Invalid
+            }
+            Tree::ListCall(var, index) => {
+                let var_obj = self.interpret(var);
+                let index_obj = self.interpret(index);
+                if let Some(err) = first_error([&var_obj, &index_obj]) {
+                    return err;
+                }
+                if let Object::Map(_) = var_obj {
+                    return var_obj
+                        .get_map_key(&index_obj.get_string_value())
+                        .unfrozen();
+                }
+                // `list[start..end]` slices, the end is exclusive
+                if let Object::Range(start, end, step) = index_obj {
+                    if step != default_step(start, end) {
+                        return Object::error(format!("Can't slice with {index_obj}"));
+                    }
+                    return match (to_int(start), to_int(end)) {
+                        (Some(start), Some(end)) => var_obj.slice(start, end),
+                        _ => Object::Null,
+                    };
+                }
+                match index_obj.to_number_obj().get_int_value() {
+                    Some(i) => var_obj.get_list_index(i).unfrozen(),
+                    None => Object::Null,
+                }
+            }
+            Tree::Ret(expr) => match self.interpret(expr) {
+                err @ Object::Error { .. } => err,
+                obj => Object::Ret(Box::new(obj)),
+            },
+            Tree::BinOp(left, op, right, _) => {
                 let left_obj = self.interpret(left);
                 let right_obj = self.interpret(right);
+                if let Some(err) = first_error([&left_obj, &right_obj]) {
+                    return err;
+                }
                 self.bin_op(left_obj, op, right_obj)
             }
+            Tree::CmpOp(left, op @ (TokenType::And | TokenType::Or), right) => {
+                // Only evaluate the right side when it decides the result
+                let left_obj = self.interpret(left);
+                if let Object::Error { .. } = left_obj {
+                    return left_obj;
+                }
+                let left_bool = left_obj.to_bool_obj().get_bool_value();
+                match (op, left_bool) {
+                    (TokenType::And, false) | (TokenType::Or, true) => {
+                        return Object::Bool(left_bool)
+                    }
+                    _ => {}
+                }
+                match self.interpret(right) {
+                    err @ Object::Error { .. } => err,
+                    right_obj => right_obj.to_bool_obj(),
+                }
+            }
             Tree::CmpOp(left, op, right) => {
                 let left_obj = self.interpret(left);
                 let right_obj = self.interpret(right);
+                if let Some(err) = first_error([&left_obj, &right_obj]) {
+                    return err;
+                }
                 self.cmp_op(left_obj, op, right_obj)
             }
 
             Tree::Let(var, value) => {
                 let v_obj = self.interpret(value);
-                let value_obj = if let Object::Ret(expr) = v_obj {
-                    *expr
-                } else {
-                    v_obj
+                let value_obj = match v_obj {
+                    Object::Ret(expr) => *expr,
+                    Object::Error { .. } => return v_obj,
+                    _ => v_obj,
                 };
-                self.set_var(&var, value_obj);
+                self.set_var(var, value_obj);
+                Object::Null
+            }
+
+            Tree::Rest(name, _) => Object::error(format!(
+                "...{name} only works as the last parameter of a fn"
+            )),
+
+            // Missing items bind null, extra items are ignored
+            Tree::Destructure(names, value) => {
+                let value_obj = match self.interpret(value) {
+                    Object::Ret(expr) => *expr,
+                    v_obj => v_obj,
+                };
+                let items = match value_obj {
+                    Object::List(items) => items,
+                    err @ Object::Error { .. } => return err,
+                    other => {
+                        return Object::error(format!(
+                            "Can't destructure a {} into [{}]",
+                            other.type_name(),
+                            names.join(", ")
+                        ))
+                    }
+                };
+                let mut items = items.into_iter();
+                for name in names {
+                    let value = items.next().unwrap_or(Object::Null);
+                    self.set_var(name, value);
+                }
                 Object::Null
             }
 
@@ -263,29 +996,58 @@ impl Interpreter {
                 if let Object::Ret(expr) = &mut value_obj {
                     value_obj = std::mem::take(expr);
                 }
+                if let Object::Error { .. } = value_obj {
+                    return value_obj;
+                }
 
                 match &**var {
-                    Tree::Ident(ref name) => {
+                    Tree::Ident(ref name, _) => {
+                        // Only the innermost variable with the name, a parameter
+                        // shadowing a global leaves the global alone
                         for scope in self.scopes.iter_mut().rev() {
                             if let Some(existing_value) = scope.get_mut(name) {
                                 if matches!(existing_value, Object::Fn { .. }) {
                                     return Object::Invalid;
                                 }
                                 *existing_value = value_obj.clone();
+                                break;
                             }
                         }
                     }
                     Tree::ListCall(var, index) => {
-                        let index_num =
-                            self.interpret(&index).to_number_obj().get_number_value() as usize;
+                        let index_obj = self.interpret(index);
+                        if let Object::Error { .. } = index_obj {
+                            return index_obj;
+                        }
+                        if let Some(err) = self.frozen_error(var) {
+                            return err;
+                        }
 
-                        if let Some(var_obj) = self.interpret_mut(&var) {
-                            var_obj.set_list_index(index_num, value_obj.clone());
+                        if let Some(var_obj) = self.interpret_mut(var) {
+                            if let Object::Map(_) = var_obj {
+                                var_obj
+                                    .set_map_key(index_obj.get_string_value(), value_obj.clone());
+                            } else {
+                                if let Some(i) = index_obj.to_number_obj().get_int_value() {
+                                    if let Err(err) = var_obj.set_list_index(i, value_obj.clone()) {
+                                        return err;
+                                    }
+                                }
+                            }
                         }
                     }
-                    Tree::MemberAccess { .. } => {
-                        let field = self.interpret_mut(var).unwrap();
-                        *field = value_obj.clone();
+                    Tree::MemberAccess { target, .. } => {
+                        if let Some(err) = self.frozen_error(target) {
+                            return err;
+                        }
+                        match self.interpret_mut(var) {
+                            Some(field) => *field = value_obj.clone(),
+                            None => {
+                                return Object::error(
+                                    "Can only assign to existing fields of stored instances",
+                                )
+                            }
+                        }
                     }
 
                     _ => {}
@@ -295,13 +1057,17 @@ impl Interpreter {
             }
 
             Tree::Fn { name, args, body } => {
+                let (rest, args) = match args.split_last() {
+                    Some((Tree::Rest(rest, _), named)) => (Some(rest.clone()), named),
+                    _ => (None, args.as_slice()),
+                };
                 let args_names: Vec<(String, Object)> = args
                     .iter()
                     .filter_map(|arg| match arg {
-                        Tree::Ident(var) => Some((var.clone(), Object::Null)),
+                        Tree::Ident(var, _) => Some((var.clone(), Object::Null)),
                         Tree::Assign(var, expr) => {
-                            if let Tree::Ident(name) = &**var {
-                                Some((name.to_string(), self.interpret(&expr)))
+                            if let Tree::Ident(name, _) = &**var {
+                                Some((name.to_string(), self.interpret(expr)))
                             } else {
                                 None
                             }
@@ -319,23 +1085,28 @@ impl Interpreter {
                 let function = Object::Fn {
                     name: name.to_string(),
                     args: args_names,
+                    rest,
                     body: body.to_vec(),
+                    captured: Box::new(self.capture_locals(body)),
                 };
-                self.set_var(&name, function).clone()
+                // Anonymous functions are only values
+                if name.is_empty() {
+                    return function;
+                }
+                self.set_var(name, function).clone()
             }
 
             Tree::FnCall {
                 name,
                 args: call_args,
+                ..
             } => {
                 // Attempt to retrieve the function object
-                let var = self.get_var(name);
-                if var.is_some() {
-                    let obj = var.unwrap().clone();
+                if let Some(var) = self.get_var(name) {
+                    let obj = var.clone();
                     self.call_function(&obj, call_args, None)
                 } else {
-                    println!("{name} is not a function");
-                    Object::Null
+                    Object::error(format!("{name} is not a function"))
                 }
             }
 
@@ -345,22 +1116,63 @@ impl Interpreter {
                 els,
                 els_ifs,
             } => {
+                let cond = self.interpret(expr);
+                if let Object::Error { .. } = cond {
+                    return cond;
+                }
                 self.enter_scope();
-                let result = if self.interpret(expr).to_bool_obj().get_bool_value() {
+                let result = if cond.to_bool_obj().get_bool_value() {
                     self.eval_block(body)
                 } else {
-                    els_ifs
-                        .iter()
-                        .find_map(|ei| match ei {
-                            Tree::ElsIf { expr, body }
-                                if self.interpret(expr).to_bool_obj().get_bool_value() =>
-                            {
-                                Some(self.eval_block(body))
+                    // `elsif` branches are checked in source order, the first match
+                    // or failing condition wins
+                    let mut taken = None;
+                    for ei in els_ifs {
+                        if let Tree::ElsIf { expr, body } = ei {
+                            let cond = self.interpret(expr);
+                            if let Object::Error { .. } = cond {
+                                taken = Some(cond);
+                                break;
                             }
-                            _ => None,
-                        })
-                        .unwrap_or_else(|| self.eval_block(els))
+                            if cond.to_bool_obj().get_bool_value() {
+                                taken = Some(self.eval_block(body));
+                                break;
+                            }
+                        }
+                    }
+                    taken.unwrap_or_else(|| self.eval_block(els))
+                };
+                self.exit_scope();
+                result
+            }
+
+            // An error in `body` runs `catch` with the error bound as a map
+            // holding `msg`, plus `line` and `column` when known. The error
+            // object itself isn't bound since using it would raise it again
+            Tree::Try {
+                body,
+                err_name,
+                catch,
+            } => {
+                self.enter_scope();
+                let result = self.eval_block(body);
+                self.exit_scope();
+
+                let (msg, loc) = match result {
+                    Object::Error { msg, loc } => (msg, loc),
+                    // `ret` and other results pass through
+                    result => return result,
                 };
+                let mut caught = FxHashMap::default();
+                caught.insert("msg".to_string(), Object::String(msg));
+                if let Some(loc) = loc {
+                    caught.insert("line".to_string(), Object::Number(loc.y as f64));
+                    caught.insert("column".to_string(), Object::Number(loc.x as f64));
+                }
+
+                self.enter_scope();
+                self.set_var(err_name, Object::Map(caught));
+                let result = self.eval_block(catch);
                 self.exit_scope();
                 result
             }
@@ -368,10 +1180,29 @@ impl Interpreter {
             Tree::While { expr, body } => {
                 self.enter_scope();
 
-                while self.interpret(expr).to_bool_obj().get_bool_value() {
-                    if let Object::Ret(v) = self.eval_block(&body) {
+                loop {
+                    if let Some(err) = Self::interrupted() {
                         self.exit_scope();
-                        return *v;
+                        return err;
+                    }
+                    match self.interpret(expr) {
+                        err @ Object::Error { .. } => {
+                            self.exit_scope();
+                            return err;
+                        }
+                        cond if !cond.to_bool_obj().get_bool_value() => break,
+                        _ => {}
+                    }
+                    match self.eval_block(body) {
+                        Object::Ret(v) => {
+                            self.exit_scope();
+                            return *v;
+                        }
+                        err @ Object::Error { .. } => {
+                            self.exit_scope();
+                            return err;
+                        }
+                        _ => {}
                     }
                 }
 
@@ -386,24 +1217,46 @@ impl Interpreter {
             } => {
                 let obj = self.interpret(expr);
                 let iter: Box<dyn Iterator<Item = Object>> = match obj {
-                    Object::Range(start, end) => Box::new(
-                        ((start as i32)..(end as i32)).map(|n: i32| Object::Number(n as f64)),
-                    ),
+                    Object::Error { .. } => return obj,
+                    Object::Range(start, end, step) => match range_items(start, end, step) {
+                        Some(items) => Box::new(items.map(|n| Object::Number(n as f64))),
+                        None => {
+                            return Object::error(format!(
+                                "Range {obj} is out of the integer range"
+                            ))
+                        }
+                    },
                     Object::String(ref string) => Box::new(
                         string
                             .chars()
                             .map(|c| Object::String(Box::new(c.to_string()))),
                     ),
                     Object::List(list) => Box::new(list.into_iter()),
-                    _ => return Object::Null,
+                    other => {
+                        return Object::error(format!(
+                            "for can't loop over a {}",
+                            other.type_name()
+                        ))
+                    }
                 };
 
                 self.enter_scope();
                 for item in iter {
-                    self.set_var(var, item);
-                    if let Object::Ret(v) = self.eval_block(body) {
+                    if let Some(err) = Self::interrupted() {
                         self.exit_scope();
-                        return *v;
+                        return err;
+                    }
+                    self.set_var(var, item);
+                    match self.eval_block(body) {
+                        Object::Ret(v) => {
+                            self.exit_scope();
+                            return *v;
+                        }
+                        err @ Object::Error { .. } => {
+                            self.exit_scope();
+                            return err;
+                        }
+                        _ => {}
                     }
                 }
                 self.exit_scope();
@@ -445,92 +1298,262 @@ impl Interpreter {
             }
 
             Tree::StructInit { name, fields } => {
-                let mut def = self.get_var(name).unwrap().clone();
+                let mut def = match self.get_var(name) {
+                    Some(def) => def.clone(),
+                    None => return Object::error(format!("Unknown struct {name}")),
+                };
                 if let Object::StructDef {
                     name: _,
                     fields: ref mut def_fields,
                     methods: _,
                 } = def
                 {
-                    fields.iter().for_each(|(field, value)| {
-                        def_fields.insert(field.to_string(), self.interpret(value));
-                    });
+                    for (field, value) in fields {
+                        match self.interpret(value) {
+                            err @ Object::Error { .. } => return err,
+                            value => {
+                                def_fields.insert(field.to_string(), value);
+                            }
+                        }
+                    }
                     let f = *def_fields.clone();
-                    return Object::Instance {
+                    Object::Instance {
                         struct_def: Box::new(def),
                         fields: f,
-                    };
+                    }
                 } else {
                     Object::Null
                 }
             }
 
-            Tree::MemberAccess { target, member } => {
+            Tree::MemberAccess { target, member, .. } => {
                 let target_object = self.interpret(target);
+                if let Object::Error { .. } = target_object {
+                    return target_object;
+                }
                 match &**member {
-                    Tree::Ident(name) => {
+                    Tree::Ident(name, _) => {
                         return target_object
                             .get_field(name)
-                            .unwrap_or(&Object::Null)
-                            .clone();
+                            .map_or(Object::Null, |field| field.clone().unfrozen());
                     }
 
-                    Tree::FnCall { name, args } => {
+                    Tree::FnCall { name, args, .. } => {
                         let method = match target_object {
                             Object::String(_) | Object::List(_) => {
                                 // this BS but who cares
                                 match &**name {
                                     "len" => return Object::Number(target_object.get_len() as f64),
-                                    "push" => {
+                                    // `index_of` counts chars in strings like indexing
+                                    // does, it gives null when there is no match
+                                    "contains" | "index_of" => {
+                                        if args.len() != 1 {
+                                            return Object::error(format!(
+                                                "Expected 1 arg found {}",
+                                                args.len()
+                                            ));
+                                        }
                                         let value = self.interpret(&args[0]);
-                                        let target_mut = self.interpret_mut(target).unwrap();
-                                        if args.len() == 1 {
-                                            target_mut.push(value)
-                                        } else {
-                                            println!("Expected 1 arg found {}", args.len());
-                                            return Object::Null;
+                                        let index = match (target_object, value) {
+                                            (_, err @ Object::Error { .. }) => return err,
+                                            (Object::List(ref list), value) => {
+                                                list.iter().position(|item| match item {
+                                                    Object::Frozen(inner) => **inner == value,
+                                                    item => *item == value,
+                                                })
+                                            }
+                                            (Object::String(ref string), Object::String(part)) => {
+                                                string
+                                                    .find(part.as_str())
+                                                    .map(|byte| string[..byte].chars().count())
+                                            }
+                                            (_, other) => {
+                                                return Object::error(format!(
+                                                    "{name} expects a string found {}",
+                                                    other.type_name()
+                                                ))
+                                            }
+                                        };
+                                        return match (&**name, index) {
+                                            ("contains", index) => Object::Bool(index.is_some()),
+                                            (_, Some(index)) => Object::Number(index as f64),
+                                            (_, None) => Object::Null,
+                                        };
+                                    }
+                                    "push" => {
+                                        if args.len() != 1 {
+                                            return Object::error(format!(
+                                                "Expected 1 arg found {}",
+                                                args.len()
+                                            ));
+                                        }
+                                        let value = match self.interpret(&args[0]) {
+                                            err @ Object::Error { .. } => return err,
+                                            value => value,
+                                        };
+                                        if let Some(err) = self.frozen_error(target) {
+                                            return err;
                                         }
+                                        let mut temporary = target_object.clone();
+                                        let target_mut = match self.interpret_mut(target) {
+                                            Some(target_mut) => target_mut,
+                                            None => &mut temporary,
+                                        };
+                                        target_mut.push(value)
                                     }
                                     "pop" => {
-                                        let target_mut = self.interpret_mut(target).unwrap();
+                                        if let Some(err) = self.frozen_error(target) {
+                                            return err;
+                                        }
+                                        let mut temporary = target_object.clone();
+                                        let target_mut = match self.interpret_mut(target) {
+                                            Some(target_mut) => target_mut,
+                                            None => &mut temporary,
+                                        };
                                         return target_mut.pop();
                                     }
-                                    _ => {}
+                                    // Queue use: `enqueue` adds at the end like `push`,
+                                    // `dequeue` removes from the front
+                                    "enqueue" | "dequeue"
+                                        if matches!(target_object, Object::List(_)) =>
+                                    {
+                                        if name == "enqueue" && args.len() != 1 {
+                                            return Object::error(format!(
+                                                "Expected 1 arg found {}",
+                                                args.len()
+                                            ));
+                                        }
+                                        let value = match args.first() {
+                                            Some(arg) => self.interpret(arg),
+                                            None => Object::Null,
+                                        };
+                                        if let Some(err) = first_error([&value]) {
+                                            return err;
+                                        }
+                                        if let Some(err) = self.frozen_error(target) {
+                                            return err;
+                                        }
+                                        // A temporary like `[1, 2].dequeue()` has no
+                                        // variable to change, its copy is used instead
+                                        let mut temporary = target_object.clone();
+                                        let target_mut = match self.interpret_mut(target) {
+                                            Some(target_mut) => target_mut,
+                                            None => &mut temporary,
+                                        };
+                                        if name == "dequeue" {
+                                            return target_mut.dequeue();
+                                        }
+                                        target_mut.push(value)
+                                    }
+                                    _ => {
+                                        if let Object::List(ref list) = target_object {
+                                            return self.list_method(list, name, args);
+                                        }
+                                        if let Object::String(ref string) = target_object {
+                                            return self.string_method(string, name, args);
+                                        }
+                                    }
                                 }
                                 Object::Null
                             }
-                            Object::Instance { ref struct_def, .. } => {
-                                if let Object::StructDef { methods, .. } = &**struct_def {
-                                    return self.call_function(
-                                        methods.get(name).unwrap(),
-                                        args,
-                                        Some(&target_object),
-                                    );
+                            Object::Instance {
+                                ref struct_def,
+                                ref fields,
+                            } => {
+                                if let Object::StructDef {
+                                    methods,
+                                    name: struct_name,
+                                    ..
+                                } = &**struct_def
+                                {
+                                    return match methods.get(name) {
+                                        Some(method) => {
+                                            self.call_method(method, args, target, &target_object)
+                                        }
+                                        None if name == "get" => self.get_or_default(fields, args),
+                                        None if name == "to_json" => {
+                                            json::stringify(&target_object)
+                                                .map_or_else(Object::error, |text| {
+                                                    Object::String(Box::new(text))
+                                                })
+                                        }
+                                        None => Object::error(format!(
+                                            "{struct_name} has no method {name}"
+                                        )),
+                                    };
                                 } else {
                                     Object::Null
                                 }
                             }
-                            Object::StructDef { ref methods, .. } => {
-                                return self.call_function(
-                                    methods.get(name).unwrap(),
-                                    args,
-                                    Some(&target_object),
-                                );
+                            Object::StructDef {
+                                ref methods,
+                                name: ref struct_name,
+                                ..
+                            } => {
+                                return match methods.get(name) {
+                                    Some(method) => {
+                                        self.call_function(method, args, Some(&target_object))
+                                    }
+                                    // `Struct::from(map)`, unless the struct defines its own
+                                    None if name == "from" => {
+                                        let values = match args.first() {
+                                            Some(arg) => self.interpret(arg),
+                                            None => Object::Null,
+                                        };
+                                        match values {
+                                            Object::Map(ref map) => {
+                                                target_object.instance_from(map)
+                                            }
+                                            Object::Error { .. } => values,
+                                            _ => Object::error(format!(
+                                                "from expects a map found {}",
+                                                values.type_name()
+                                            )),
+                                        }
+                                    }
+                                    None if name == "from_json" => {
+                                        let text = match args.first() {
+                                            Some(arg) => self.interpret(arg),
+                                            None => Object::Null,
+                                        };
+                                        match text {
+                                            Object::String(ref text) => {
+                                                json::parse_instance(text, &target_object)
+                                                    .unwrap_or_else(Object::error)
+                                            }
+                                            Object::Error { .. } => text,
+                                            _ => Object::error(format!(
+                                                "from_json expects a string found {}",
+                                                text.type_name()
+                                            )),
+                                        }
+                                    }
+                                    None => {
+                                        Object::error(format!("{struct_name} has no method {name}"))
+                                    }
+                                };
+                            }
+                            Object::Map(ref map) => {
+                                return self.map_method(map, name, args);
+                            }
+                            Object::Heap(ref heap) => {
+                                return self.heap_method(heap, target, name, args);
+                            }
+                            Object::Range(start, end, step) => {
+                                return self.range_method((start, end, step), name, args);
                             }
                             Object::NameSpace {
                                 ref namespace,
                                 name: ref namespace_name,
                             } => {
-                                return self.call_function(
-                                    namespace.get(name).expect(
-                                        format!(
-                                            "function {name} doesn't exist in {namespace_name}",
-                                        )
-                                        .as_str(),
-                                    ),
-                                    args,
-                                    Some(&target_object),
-                                );
+                                return match namespace.get(name) {
+                                    Some(function) => {
+                                        self.call_function(function, args, Some(&target_object))
+                                    }
+                                    None => Object::error(format!(
+                                        "function {name} doesn't exist in {namespace_name}"
+                                    )),
+                                };
                             }
                             _ => Object::Null,
                         };
@@ -545,39 +1568,55 @@ impl Interpreter {
 
             Tree::Import { path, alias } => {
                 if let Tree::MemberAccess { .. } = &**path {
-                    let flat_path = self.flatten_path(path);
-                    let root_path = format!("{}/{}.iok", self.std_path, flat_path[0]);
-                    let root_namespace = self.import_file_to_namespace(&root_path);
+                    let flat_path = match self.flatten_path(path) {
+                        Ok(flat_path) => flat_path,
+                        Err(err) => return err,
+                    };
+                    let root_path = self.std_lib_path(&flat_path[0]);
+                    let root_namespace = match self.import_file_to_namespace(&root_path) {
+                        Ok(namespace) => namespace,
+                        Err(err) => return err,
+                    };
 
                     let mut scope = root_namespace;
 
                     let mut current_obj = Object::Null;
                     for (i, seg) in flat_path.iter().enumerate().skip(1) {
-                        let val = scope
-                            .get(seg)
-                            .unwrap_or_else(|| {
-                                panic!("`{}` not found in `{}`", seg, flat_path[..i].join("::"))
-                            })
-                            .clone();
+                        let val = match scope.get(seg) {
+                            Some(val) => val.clone(),
+                            None => {
+                                return Object::error(format!(
+                                    "`{}` not found in `{}`",
+                                    seg,
+                                    flat_path[..i].join("::")
+                                ))
+                            }
+                        };
                         if i < flat_path.len() - 1 {
                             match val {
                                 Object::NameSpace { namespace, .. } => {
                                     scope = *namespace; // enter that namespace
                                 }
-                                _ => panic!("`{}` is not a namespace", seg),
+                                _ => return Object::error(format!("`{}` is not a namespace", seg)),
                             }
                         } else {
                             current_obj = val;
                         }
                     }
-                    let bind_name = alias.as_deref().unwrap_or(&flat_path.last().unwrap());
+                    let bind_name = alias.as_deref().unwrap_or(flat_path.last().unwrap());
 
-                    self.set_var(&bind_name, current_obj);
+                    self.set_var(bind_name, current_obj);
                     return Object::Null;
                 }
 
-                let file_path = self.resolve_import_path(&**path);
-                let namespace = self.import_file_to_namespace(&file_path);
+                let file_path = match self.resolve_import_path(path) {
+                    Ok(file_path) => file_path,
+                    Err(err) => return err,
+                };
+                let namespace = match self.import_file_to_namespace(&file_path) {
+                    Ok(namespace) => namespace,
+                    Err(err) => return err,
+                };
 
                 if let Some(name) = alias {
                     let bind_name = name.as_str();
@@ -599,20 +1638,24 @@ impl Interpreter {
     // A Helper Method to mut Objects
     fn interpret_mut(&mut self, tree: &Tree) -> Option<&mut Object> {
         match tree {
-            Tree::Ident(name) => self.get_var(&name), // Return a mutable reference to the variable
+            Tree::Ident(name, _) => self.get_var(name), // Return a mutable reference to the variable
             Tree::ListCall(list, index) => {
-                let index_num = self.interpret(index).to_number_obj().get_number_value() as usize;
+                let index_obj = self.interpret(index);
                 if let Some(list_obj) = self.interpret_mut(list) {
+                    if let Object::Map(_) = list_obj {
+                        return list_obj.get_map_key_mut(&index_obj.get_string_value());
+                    }
                     // Get a mutable reference to the object at the specified index in the list
+                    let index_num = index_obj.to_number_obj().get_int_value()?;
                     list_obj.get_list_index_mut(index_num)
                 } else {
                     None
                 }
             }
-            Tree::MemberAccess { target, member } => {
-                let target_obj = self.interpret_mut(target).unwrap();
+            Tree::MemberAccess { target, member, .. } => {
+                let target_obj = self.interpret_mut(target)?;
 
-                if let Tree::Ident(field_name) = &**member {
+                if let Tree::Ident(field_name, _) = &**member {
                     return target_obj.get_field_mut(field_name);
                 }
                 None
@@ -622,11 +1665,502 @@ impl Interpreter {
         }
     }
 
+    // List methods that need the interpreter, e.g. to call a function argument
+    fn list_method(&mut self, list: &[Object], name: &str, args: &[Tree]) -> Object {
+        match name {
+            // Keys are ordered by `Object::compare`, numbers numerically and strings
+            // lexicographically. A key that can't be compared with the current best
+            // is skipped, and the first element wins on ties
+            "min_by" | "max_by" => {
+                if args.len() != 1 {
+                    return Object::error(format!("Expected 1 arg found {}", args.len()));
+                }
+                let key_fn = match self.callable_arg(name, &args[0]) {
+                    Ok(f) => f,
+                    Err(err) => return err,
+                };
+                let wanted = if name == "min_by" {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+
+                let mut best: Option<(Object, &Object)> = None;
+                for item in list {
+                    let key = self.call_function_with(&key_fn, vec![item.clone()], None);
+                    if let Object::Error { .. } = key {
+                        return key;
+                    }
+                    let is_better = match &best {
+                        Some((best_key, _)) => key.compare(best_key) == Some(wanted),
+                        None => true,
+                    };
+                    if is_better {
+                        best = Some((key, item));
+                    }
+                }
+                best.map_or(Object::Null, |(_, item)| item.clone())
+            }
+            "sum_by" => {
+                if args.len() != 1 {
+                    return Object::error(format!("Expected 1 arg found {}", args.len()));
+                }
+                let key_fn = match self.callable_arg(name, &args[0]) {
+                    Ok(f) => f,
+                    Err(err) => return err,
+                };
+                let mut sum = 0.0;
+                for item in list {
+                    match self.call_function_with(&key_fn, vec![item.clone()], None) {
+                        Object::Number(n) => sum += n,
+                        err @ Object::Error { .. } => return err,
+                        _ => return Object::Invalid,
+                    }
+                }
+                Object::Number(sum)
+            }
+            "product" => {
+                let mut product = 1.0;
+                for item in list {
+                    match item {
+                        Object::Number(n) => product *= n,
+                        _ => return Object::Invalid,
+                    }
+                }
+                Object::Number(product)
+            }
+            "map" | "filter" => {
+                if args.len() != 1 {
+                    return Object::error(format!("Expected 1 arg found {}", args.len()));
+                }
+                let function = match self.callable_arg(name, &args[0]) {
+                    Ok(f) => f,
+                    Err(err) => return err,
+                };
+                let mut result = vec![];
+                for item in list {
+                    let value = self.call_function_with(&function, vec![item.clone()], None);
+                    if let Object::Error { .. } = value {
+                        return value;
+                    }
+                    if name == "map" {
+                        result.push(value);
+                    } else if value.to_bool_obj().get_bool_value() {
+                        result.push(item.clone());
+                    }
+                }
+                Object::List(result)
+            }
+            // Returns a sorted copy. Without arguments numbers sort numerically and
+            // strings lexicographically, mixing them is an error. `sort(fn)` orders by
+            // `fn(a, b)` which returns a negative number when `a` goes first, zero
+            // when they are equal and a positive number otherwise
+            "sort" => {
+                let sorted = match args.len() {
+                    0 => merge_sort(list.to_vec(), &mut |a, b| {
+                        a.compare(b).ok_or_else(|| {
+                            Object::error("Can't sort a list of mixed types without a comparator")
+                        })
+                    }),
+                    1 => {
+                        let cmp_fn = match self.callable_arg(name, &args[0]) {
+                            Ok(f) => f,
+                            Err(err) => return err,
+                        };
+                        merge_sort(list.to_vec(), &mut |a, b| match self.call_function_with(
+                            &cmp_fn,
+                            vec![a.clone(), b.clone()],
+                            None,
+                        ) {
+                            Object::Number(n) if !n.is_nan() => Ok(n.total_cmp(&0.0)),
+                            err @ Object::Error { .. } => Err(err),
+                            other => Err(Object::error(format!(
+                                "sort comparator has to return a number found {}",
+                                other.type_name()
+                            ))),
+                        })
+                    }
+                    n => {
+                        return Object::error(format!("Expected 0 or 1 args found {n}"));
+                    }
+                };
+                match sorted {
+                    Ok(sorted) => Object::List(sorted),
+                    Err(err) => err,
+                }
+            }
+            "reverse" => Object::List(list.iter().rev().cloned().collect()),
+            // `head`, `tail` and `prepend` return new lists without changing the
+            // original, so they work on temporaries in recursive code. Like
+            // `pop` and `dequeue`, taking an item from an empty list is an error
+            "peek" | "peek_back" | "head" => {
+                let item = if name == "peek_back" {
+                    list.last()
+                } else {
+                    list.first()
+                };
+                item.cloned()
+                    .unwrap_or_else(|| Object::error(format!("Can't {name} an empty list")))
+            }
+            "tail" => Object::List(list.iter().skip(1).cloned().collect()),
+            "prepend" => {
+                if args.len() != 1 {
+                    return Object::error(format!("Expected 1 arg found {}", args.len()));
+                }
+                let value = match self.interpret(&args[0]) {
+                    err @ Object::Error { .. } => return err,
+                    value => value,
+                };
+                let mut prepended = Vec::with_capacity(list.len() + 1);
+                prepended.push(value);
+                prepended.extend_from_slice(list);
+                Object::List(prepended)
+            }
+            "join" => {
+                if args.len() != 1 {
+                    return Object::error(format!("Expected 1 arg found {}", args.len()));
+                }
+                let separator = match self.interpret(&args[0]) {
+                    err @ Object::Error { .. } => return err,
+                    separator => separator.get_string_value(),
+                };
+                let parts: Vec<String> = list
+                    .iter()
+                    .map(|item| item.to_string_obj().get_string_value())
+                    .collect();
+                Object::String(Box::new(parts.join(&separator)))
+            }
+            // Folds from the first element calling `fn(accumulator, element)`
+            "reduce" => {
+                if args.len() != 2 {
+                    return Object::error(format!("Expected 2 args found {}", args.len()));
+                }
+                let fold_fn = match self.callable_arg(name, &args[0]) {
+                    Ok(f) => f,
+                    Err(err) => return err,
+                };
+                let init = self.interpret(&args[1]);
+                let mut acc = init;
+                for item in list {
+                    if let Object::Error { .. } = acc {
+                        break;
+                    }
+                    acc = self.call_function_with(&fold_fn, vec![acc, item.clone()], None);
+                }
+                acc
+            }
+            // Folds from the last element to the first. Unlike a left fold the
+            // callback gets the element first: `fn(element, accumulator)`
+            "reduce_right" => {
+                if args.len() != 2 {
+                    return Object::error(format!("Expected 2 args found {}", args.len()));
+                }
+                let init = self.interpret(&args[0]);
+                let fold_fn = match self.callable_arg(name, &args[1]) {
+                    Ok(f) => f,
+                    Err(err) => return err,
+                };
+                let mut acc = init;
+                for item in list.iter().rev() {
+                    if let Object::Error { .. } = acc {
+                        break;
+                    }
+                    acc = self.call_function_with(&fold_fn, vec![item.clone(), acc], None);
+                }
+                acc
+            }
+            _ => Object::error(format!("list has no method {name}")),
+        }
+    }
+
+    fn map_method(&mut self, map: &FxHashMap<String, Object>, name: &str, args: &[Tree]) -> Object {
+        match name {
+            "len" => Object::Number(map.len() as f64),
+            "get" => self.get_or_default(map, args),
+            // Sorted by key, the order maps print in
+            "entries" => {
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                Object::List(
+                    keys.into_iter()
+                        .map(|key| {
+                            let key_obj = Object::String(Box::new(key.clone()));
+                            Object::List(vec![key_obj, map[key].clone()])
+                        })
+                        .collect(),
+                )
+            }
+            // Shallow, a key in both maps takes the value from `other` even when
+            // both values are maps
+            "merge" => {
+                if args.len() != 1 {
+                    return Object::error(format!("Expected 1 arg found {}", args.len()));
+                }
+                match self.interpret(&args[0]).unfrozen() {
+                    Object::Map(other) => {
+                        let mut merged = map.clone();
+                        merged.extend(other);
+                        Object::Map(merged)
+                    }
+                    err @ Object::Error { .. } => err,
+                    other => {
+                        Object::error(format!("merge expects a map found {}", other.type_name()))
+                    }
+                }
+            }
+            _ => Object::error(format!("map has no method {name}")),
+        }
+    }
+
+    // `get(key, default?)` of maps and instances, the default is used when the
+    // key is missing and is null when not given
+    fn get_or_default(&mut self, values: &FxHashMap<String, Object>, args: &[Tree]) -> Object {
+        if args.is_empty() || args.len() > 2 {
+            return Object::error(format!("Expected 1 or 2 args found {}", args.len()));
+        }
+        let key = self.interpret(&args[0]);
+        if let Object::Error { .. } = key {
+            return key;
+        }
+        match values.get(&key.get_string_value()) {
+            Some(value) => value.clone().unfrozen(),
+            None => match args.get(1) {
+                Some(default) => self.interpret(default),
+                None => Object::Null,
+            },
+        }
+    }
+
+    // `intersect` and `union` treat ranges as the intervals from start up to
+    // but not including end, so both need ascending ranges without a step
+    fn range_method(&mut self, range: (f64, f64, f64), name: &str, args: &[Tree]) -> Object {
+        match name {
+            "intersect" | "union" => {
+                if args.len() != 1 {
+                    return Object::error(format!("Expected 1 arg found {}", args.len()));
+                }
+                let other = match self.interpret(&args[0]) {
+                    Object::Range(start, end, step) => (start, end, step),
+                    err @ Object::Error { .. } => return err,
+                    other => {
+                        return Object::error(format!(
+                            "{name} expects a range found {}",
+                            other.type_name()
+                        ))
+                    }
+                };
+                let is_interval = |(start, end, step): (f64, f64, f64)| start <= end && step == 1.0;
+                if !is_interval(range) || !is_interval(other) {
+                    return Object::error(format!("{name} needs ascending ranges without a step"));
+                }
+                let ((l_start, l_end, _), (r_start, r_end, _)) = (range, other);
+                if name == "intersect" {
+                    // Disjoint ranges, or ranges that only touch, share nothing
+                    let (start, end) = (l_start.max(r_start), l_end.min(r_end));
+                    return if start < end {
+                        Object::Range(start, end, 1.0)
+                    } else {
+                        Object::Null
+                    };
+                }
+                // The smallest range covering both, an empty range adds nothing
+                match (l_start < l_end, r_start < r_end) {
+                    (false, _) => Object::Range(r_start, r_end, 1.0),
+                    (_, false) => Object::Range(l_start, l_end, 1.0),
+                    _ => Object::Range(l_start.min(r_start), l_end.max(r_end), 1.0),
+                }
+            }
+            // Walk the numbers one by one without building a list first, `each`
+            // calls fn only for its effects
+            "map" | "filter" | "each" => {
+                if args.len() != 1 {
+                    return Object::error(format!("Expected 1 arg found {}", args.len()));
+                }
+                let function = match self.callable_arg(name, &args[0]) {
+                    Ok(f) => f,
+                    Err(err) => return err,
+                };
+                let (start, end, step) = range;
+                let Some(items) = range_items(start, end, step) else {
+                    return Object::error(format!(
+                        "Range {} is out of the integer range",
+                        Object::Range(start, end, step)
+                    ));
+                };
+                let mut result = vec![];
+                for n in items {
+                    let item = Object::Number(n as f64);
+                    let value = self.call_function_with(&function, vec![item.clone()], None);
+                    if let Object::Error { .. } = value {
+                        return value;
+                    }
+                    match name {
+                        "map" => result.push(value),
+                        "filter" if value.to_bool_obj().get_bool_value() => result.push(item),
+                        _ => {}
+                    }
+                }
+                if name == "each" {
+                    return Object::Null;
+                }
+                Object::List(result)
+            }
+            _ => Object::error(format!("range has no method {name}")),
+        }
+    }
+
+    // `push`, `pop_min` and `pop_max` change the heap stored in `target`, on a
+    // temporary they act on a copy
+    fn heap_method(&mut self, heap: &Heap, target: &Tree, name: &str, args: &[Tree]) -> Object {
+        match name {
+            "len" => Object::Number(heap.len() as f64),
+            "is_empty" => Object::Bool(heap.is_empty()),
+            "peek_min" => heap.peek_min().cloned().unwrap_or(Object::Null),
+            "peek_max" => heap.peek_max().cloned().unwrap_or(Object::Null),
+            "to_list" => Object::List(heap.values().cloned().collect()),
+            "push" | "pop_min" | "pop_max" => {
+                let pushed = if name == "push" {
+                    // The priority defaults to the value itself for numbers
+                    let (value, priority) = match args {
+                        [value] => {
+                            let value = self.interpret(value);
+                            let priority = value.clone();
+                            (value, priority)
+                        }
+                        [value, priority] => (self.interpret(value), self.interpret(priority)),
+                        _ => {
+                            return Object::error(format!(
+                                "Expected 1 or 2 args found {}",
+                                args.len()
+                            ))
+                        }
+                    };
+                    if let Some(err) = first_error([&value, &priority]) {
+                        return err;
+                    }
+                    match priority {
+                        Object::Number(priority) => Some((value, priority)),
+                        other => {
+                            return Object::error(format!(
+                                "Heap priority must be a number found {}",
+                                other.type_name()
+                            ))
+                        }
+                    }
+                } else {
+                    None
+                };
+                if let Some(err) = self.frozen_error(target) {
+                    return err;
+                }
+
+                let mut copy = Object::Heap(Box::new(heap.clone()));
+                let target_mut = self.interpret_mut(target).unwrap_or(&mut copy);
+                let Object::Heap(heap) = target_mut else {
+                    return Object::Null;
+                };
+                match pushed {
+                    Some((value, priority)) => {
+                        heap.push(value, priority);
+                        Object::Null
+                    }
+                    None if name == "pop_min" => heap.pop_min().unwrap_or(Object::Null),
+                    None => heap.pop_max().unwrap_or(Object::Null),
+                }
+            }
+            _ => Object::error(format!("heap has no method {name}")),
+        }
+    }
+
+    fn string_method(&mut self, string: &str, name: &str, args: &[Tree]) -> Object {
+        match name {
+            // An empty separator splits into characters
+            "split" => {
+                if args.len() != 1 {
+                    return Object::error(format!("Expected 1 arg found {}", args.len()));
+                }
+                let separator = match self.interpret(&args[0]) {
+                    err @ Object::Error { .. } => return err,
+                    separator => separator.get_string_value(),
+                };
+                let parts: Vec<Object> = if separator.is_empty() {
+                    string
+                        .chars()
+                        .map(|c| Object::String(Box::new(c.to_string())))
+                        .collect()
+                } else {
+                    string
+                        .split(separator.as_str())
+                        .map(|part| Object::String(Box::new(part.to_string())))
+                        .collect()
+                };
+                Object::List(parts)
+            }
+            "trim" => Object::String(Box::new(string.trim().to_string())),
+            "reverse" => Object::String(Box::new(string.chars().rev().collect())),
+            "upper" => Object::String(Box::new(string.to_uppercase())),
+            "lower" => Object::String(Box::new(string.to_lowercase())),
+            // Every occurrence of `from` is replaced
+            "replace" => {
+                if args.len() != 2 {
+                    return Object::error(format!("Expected 2 args found {}", args.len()));
+                }
+                match (self.interpret(&args[0]), self.interpret(&args[1])) {
+                    (err @ Object::Error { .. }, _) | (_, err @ Object::Error { .. }) => err,
+                    (Object::String(from), Object::String(to)) => {
+                        Object::String(Box::new(string.replace(from.as_str(), &to)))
+                    }
+                    (from, to) => Object::error(format!(
+                        "replace expects two strings found {} and {}",
+                        from.type_name(),
+                        to.type_name()
+                    )),
+                }
+            }
+            // Code point of the char at a char index, null when out of range
+            "code_at" => {
+                if args.len() != 1 {
+                    return Object::error(format!("Expected 1 arg found {}", args.len()));
+                }
+                let index = match self.interpret(&args[0]) {
+                    Object::Number(index) => index,
+                    err @ Object::Error { .. } => return err,
+                    other => {
+                        return Object::error(format!(
+                            "code_at expects a number found {}",
+                            other.type_name()
+                        ))
+                    }
+                };
+                if index < 0.0 || index.fract() != 0.0 {
+                    return Object::Null;
+                }
+                string
+                    .chars()
+                    .nth(index as usize)
+                    .map_or(Object::Null, |c| Object::Number(c as u32 as f64))
+            }
+            _ => Object::error(format!("string has no method {name}")),
+        }
+    }
+
+    // Evaluates a method argument that has to be a function
+    fn callable_arg(&mut self, method: &str, arg: &Tree) -> Result<Object, Object> {
+        match self.interpret(arg) {
+            function @ (Object::Fn { .. } | Object::NativeFn { .. }) => Ok(function),
+            err @ Object::Error { .. } => Err(err),
+            other => Err(Object::error(format!(
+                "{method} expects a function found {}",
+                other.type_name()
+            ))),
+        }
+    }
+
     fn eval_block(&mut self, body: &[Tree]) -> Object {
         let mut result = Object::Null;
         for stmt in body {
             result = self.interpret(stmt);
-            if let Object::Ret(_) = result {
+            if let Object::Ret(_) | Object::Error { .. } = result {
                 break;
             }
         }
@@ -635,19 +2169,117 @@ impl Interpreter {
     pub fn call_function(
         &mut self,
         function: &Object,
-        call_args: &Vec<Tree>,
+        call_args: &[Tree],
         slf: Option<&Object>,
     ) -> Object {
-        if let Object::Fn { args, body, .. } = function {
+        // Arguments are evaluated in the caller's scope before the call
+        let args_objects: Vec<Object> = call_args.iter().map(|arg| self.interpret(arg)).collect();
+        if let Some(err) = first_error(&args_objects) {
+            return err;
+        }
+        self.call_function_with(function, args_objects, slf)
+    }
+
+    // Calls a method on an instance, then writes the possibly mutated `self`
+    // back into `target`. Temporaries that aren't stored anywhere are dropped
+    fn call_method(
+        &mut self,
+        method: &Object,
+        call_args: &[Tree],
+        target: &Tree,
+        target_object: &Object,
+    ) -> Object {
+        let args_objects: Vec<Object> = call_args.iter().map(|arg| self.interpret(arg)).collect();
+        if let Some(err) = first_error(&args_objects) {
+            return err;
+        }
+        let (result, new_self) =
+            self.call_function_inner(method, args_objects, Some(target_object));
+        if let Some(new_self) = new_self.filter(|new_self| new_self != target_object) {
+            if let Some(err) = self.frozen_error(target) {
+                return err;
+            }
+            if let Some(target_mut) = self.interpret_mut(target) {
+                *target_mut = new_self;
+            }
+        }
+        result
+    }
+
+    // True if the value at `tree` or a container holding it is frozen
+    fn is_frozen(&mut self, tree: &Tree) -> bool {
+        let parent_frozen = match tree {
+            Tree::ListCall(parent, _) => self.is_frozen(parent),
+            Tree::MemberAccess { target, .. } => self.is_frozen(target),
+            _ => false,
+        };
+        parent_frozen || matches!(self.interpret_mut(tree), Some(Object::Frozen(_)))
+    }
+
+    // Error to return when changing the value at `tree` isn't allowed
+    fn frozen_error(&mut self, tree: &Tree) -> Option<Object> {
+        if self.is_frozen(tree) {
+            Some(Object::error("Can't change a frozen value"))
+        } else {
+            None
+        }
+    }
+
+    // Calls a function object with already evaluated arguments
+    pub fn call_function_with(
+        &mut self,
+        function: &Object,
+        call_args: Vec<Object>,
+        slf: Option<&Object>,
+    ) -> Object {
+        self.call_function_inner(function, call_args, slf).0
+    }
+
+    // Also returns the final value of `self` when one was bound
+    fn call_function_inner(
+        &mut self,
+        function: &Object,
+        call_args: Vec<Object>,
+        slf: Option<&Object>,
+    ) -> (Object, Option<Object>) {
+        if let Object::Fn {
+            name,
+            args,
+            rest,
+            body,
+            captured,
+        } = function
+        {
+            if self.call_depth >= self.max_call_depth {
+                let err = Object::error(format!(
+                    "Maximum call depth of {} exceeded calling {name}",
+                    self.max_call_depth
+                ));
+                return (err, None);
+            }
+            self.call_depth += 1;
+            // Captured variables get a scope of their own under the arguments,
+            // so they shadow the caller's variables and the arguments and
+            // `let`s in the body shadow them
+            if !captured.is_empty() {
+                self.enter_scope();
+                for (name, (id, value)) in captured.iter() {
+                    let value = match self.live_scope(*id).and_then(|scope| scope.get(name)) {
+                        Some(current) => current.clone(),
+                        None => value.clone(),
+                    };
+                    self.set_var(name, value);
+                }
+            }
             self.enter_scope();
-            // Bind default arguments and interpret call arguments
-            for (i, (arg_name, default_value)) in args.iter().enumerate() {
-                let value = if i < call_args.len() {
-                    self.interpret(&call_args[i])
-                } else {
-                    default_value.clone()
-                };
-                self.set_var(&arg_name, value);
+            // Bind call arguments, falling back to the defaults
+            let mut call_args = call_args.into_iter();
+            for (arg_name, default_value) in args.iter() {
+                let value = call_args.next().unwrap_or_else(|| default_value.clone());
+                self.set_var(arg_name, value);
+            }
+            if let Some(rest) = rest {
+                self.set_var(rest, Object::List(call_args.collect()));
             }
             if let Some(obj) = slf {
                 if let Object::NameSpace { namespace, .. } = obj {
@@ -660,63 +2292,107 @@ impl Interpreter {
             }
 
             // Execute the function body
-            let result = self.eval_block(&body);
+            let result = self.eval_block(body);
+            let new_self = match slf {
+                Some(Object::NameSpace { .. }) | None => None,
+                Some(_) => self
+                    .scopes
+                    .last_mut()
+                    .and_then(|scope| scope.remove("self")),
+            };
             self.exit_scope();
+            if !captured.is_empty() {
+                // Hand the final values back to a defining scope still running
+                let scope = self.scopes.pop().unwrap_or_default();
+                self.scope_ids.pop();
+                for (name, (id, _)) in captured.iter() {
+                    if let (Some(value), Some(scope)) = (scope.get(name), self.live_scope(*id)) {
+                        if let Some(slot) = scope.get_mut(name) {
+                            *slot = value.clone();
+                        }
+                    }
+                }
+            }
+            self.call_depth -= 1;
             // Return result or Object::Null
-            return match result {
+            let result = match result {
                 Object::Ret(expr) => *expr,
+                err @ Object::Error { .. } => err,
                 _ => Object::Null,
             };
+            return (result, new_self);
         } else if let Object::NativeFn { function, .. } = function {
-            let mut args_objects = vec![];
-            call_args.iter().for_each(|arg| {
-                args_objects.push(self.interpret(arg));
-            });
-            return function(args_objects, self);
+            return (function(call_args, self), None);
         }
-        Object::Null
+        (Object::Null, None)
     }
 
-    fn resolve_import_path(&self, path: &Tree) -> String {
-        let mut path_str = match path {
-            Tree::String(p) => self.current_path.to_string() + "\\" + &**p,
-            Tree::Ident(lib) => self.std_path.to_string() + "/" + lib + ".iok",
-            _ => panic!("Expected Path or Lib name"),
-        };
-        if cfg!(windows) {
-            path_str = path_str.replace("/", "\\");
+    // `import "dir/file.iok"` is relative to the importing file, `import lib`
+    // is `lib.iok` in the std dir. `/` in a string path works on every OS
+    fn resolve_import_path(&self, path: &Tree) -> Result<PathBuf, Object> {
+        match path {
+            Tree::String(p) => Ok(Path::new(&self.current_path).join(p.as_str())),
+            Tree::Ident(lib, _) => Ok(self.std_lib_path(lib)),
+            _ => Err(Object::error(
+                "Expected a file path or a lib name to import",
+            )),
         }
+    }
 
-        path_str
+    fn std_lib_path(&self, lib: &str) -> PathBuf {
+        Path::new(&self.std_path).join(format!("{lib}.iok"))
     }
 
-    fn flatten_path(&self, path: &Tree) -> Vec<String> {
+    fn flatten_path(&self, path: &Tree) -> Result<Vec<String>, Object> {
         match path {
-            Tree::Ident(name) => vec![name.clone()],
-            Tree::MemberAccess { target, member } => {
-                let mut parts = self.flatten_path(target);
-                if let Tree::Ident(m) = &**member {
+            Tree::Ident(name, _) => Ok(vec![name.clone()]),
+            Tree::MemberAccess { target, member, .. } => {
+                let mut parts = self.flatten_path(target)?;
+                if let Tree::Ident(m, _) = &**member {
                     parts.push(m.clone());
-                    parts
+                    Ok(parts)
                 } else {
-                    panic!("Import path member must be identifier");
+                    Err(Object::error("Import path member must be an identifier"))
                 }
             }
-            _ => panic!("Invalid import path: {:?}", path),
+            _ => Err(Object::error("Invalid import path")),
         }
     }
-    fn import_file_to_namespace(&self, file_path: &String) -> FxHashMap<String, Object> {
-        let parsed_trees = self.generate_ast(file_path);
-        let parent_path = Path::new(file_path)
+    // Runs a file the first time it is imported, later imports of the same
+    // file get the cached namespace
+    fn import_file_to_namespace(
+        &mut self,
+        file_path: &Path,
+    ) -> Result<FxHashMap<String, Object>, Object> {
+        let canonical = file_path
             .canonicalize()
-            .expect("Can't get path")
-            .parent()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
+            .map_err(|err| Object::error(format!("Can't import {}: {err}", file_path.display())))?;
+        let key = canonical.to_string_lossy().to_string();
+        if let Some(namespace) = self.modules.get(&key) {
+            return Ok(namespace.clone());
+        }
+        if let Some(start) = self.loading.iter().position(|path| *path == key) {
+            let mut cycle = self.loading[start..].to_vec();
+            cycle.push(key);
+            return Err(Object::error(format!(
+                "Circular import: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        let parsed_trees = self.generate_ast(&key)?;
+        let parent_path = canonical.parent().map_or(String::from("/"), |parent| {
+            parent.to_string_lossy().to_string()
+        });
 
-        self.eval_namespace(parent_path, &parsed_trees)
+        // A file that stopped with an error isn't cached, importing it again
+        // runs it again
+        self.loading.push(key.clone());
+        let namespace = self.eval_namespace(parent_path, &parsed_trees);
+        self.loading.pop();
+        let namespace = namespace?;
+        self.modules.insert(key, namespace.clone());
+        Ok(namespace)
     }
 
     fn import_namespace_into_scope(&mut self, namespace: FxHashMap<String, Object>) {
@@ -724,32 +2400,506 @@ impl Interpreter {
             self.set_var(&name, value);
         }
     }
-    fn generate_ast(&self, file_path: &String) -> Vec<Tree> {
+    fn generate_ast(&self, file_path: &str) -> Result<Vec<Tree>, Object> {
         let mut input = String::new();
 
-        let mut file = File::open(&file_path).expect("Can't locate lib");
-        file.read_to_string(&mut input).expect("can't read file");
+        let cant_read =
+            |err: std::io::Error| Object::error(format!("Can't import {file_path}: {err}"));
+        let mut file = File::open(file_path).map_err(cant_read)?;
+        file.read_to_string(&mut input).map_err(cant_read)?;
         input = input.trim_end().to_string();
 
         let mut lexer = Lexer::new(&input);
         let tokens = lexer.tokenize();
-        let mut parser = Parser::new(tokens);
+        let mut parser = Parser::with_source(tokens, &input);
 
-        let parsed_tree = parser.parse_tokens();
-        parsed_tree
+        Ok(parser.parse_tokens())
     }
-    fn eval_namespace(&self, path: String, parsed_trees: &Vec<Tree>) -> FxHashMap<String, Object> {
+    // Runs an imported file in its own interpreter, the first error stops it
+    // and is returned to the import
+    fn eval_namespace(
+        &mut self,
+        path: String,
+        parsed_trees: &[Tree],
+    ) -> Result<FxHashMap<String, Object>, Object> {
         let mut namespace = FxHashMap::default();
         let mut mod_interpreter = Interpreter::new(path, Option::Some(self.std_path.clone()));
-        parsed_trees.iter().for_each(|ast| {
-            mod_interpreter.interpret(ast);
-        });
+        mod_interpreter.max_call_depth = self.max_call_depth;
+        mod_interpreter.log_level = self.log_level;
+        mod_interpreter.modules = std::mem::take(&mut self.modules);
+        mod_interpreter.loading = std::mem::take(&mut self.loading);
+        let mut failed = None;
+        for ast in parsed_trees {
+            let result = mod_interpreter.interpret(ast);
+            if let Object::Error { .. } = result {
+                failed = Some(result);
+                break;
+            }
+        }
+        self.modules = std::mem::take(&mut mod_interpreter.modules);
+        self.loading = std::mem::take(&mut mod_interpreter.loading);
+        if let Some(err) = failed {
+            return Err(err);
+        }
 
         if let Some(scope) = mod_interpreter.scopes.first() {
             for (n, value) in scope {
                 namespace.insert(n.clone(), value.clone());
             }
         }
-        namespace
+        Ok(namespace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(src: &str) -> Object {
+        Interpreter::new(".".to_string(), None).eval_str(src)
+    }
+
+    fn numbers(items: &[f64]) -> Object {
+        Object::List(items.iter().copied().map(Object::Number).collect())
+    }
+
+    fn is_error(obj: &Object) -> bool {
+        matches!(obj, Object::Error { .. })
+    }
+
+    #[test]
+    fn map_filter_reduce_with_named_functions() {
+        let prelude = "fn double(x) => x * 2\nfn odd(x) => x - math.floor_div(x, 2) * 2 == 1\nfn add(a, b) => a + b\nlet xs = [1, 2, 3, 4]\n";
+        assert_eq!(
+            eval(&format!("{prelude}xs.map(double)")),
+            numbers(&[2.0, 4.0, 6.0, 8.0])
+        );
+        assert_eq!(
+            eval(&format!("{prelude}xs.filter(odd)")),
+            numbers(&[1.0, 3.0])
+        );
+        assert_eq!(
+            eval(&format!("{prelude}xs.reduce(add, 10)")),
+            Object::Number(20.0)
+        );
+    }
+
+    #[test]
+    fn map_filter_reduce_with_closures() {
+        let prelude = "fn adder(n) => fn(x) => x + n\nlet xs = [1, 2, 3]\n";
+        assert_eq!(
+            eval(&format!("{prelude}xs.map(adder(10))")),
+            numbers(&[11.0, 12.0, 13.0])
+        );
+        assert_eq!(
+            eval(&format!("{prelude}xs.filter(fn(x) => x > 1)")),
+            numbers(&[2.0, 3.0])
+        );
+        assert_eq!(
+            eval(&format!("{prelude}xs.reduce(fn(acc, x) => acc * x, 1)")),
+            Object::Number(6.0)
+        );
+    }
+
+    #[test]
+    fn closures_outlive_the_defining_call() {
+        let src = "fn make_adder(n) => fn(x) => x + n\nlet add10 = make_adder(10)\nadd10(5)";
+        assert_eq!(eval(src), Object::Number(15.0));
+    }
+
+    #[test]
+    fn captured_variables_win_over_the_call_site() {
+        let prelude = "fn make_adder(n) => fn(x) => x + n\nlet add10 = make_adder(10)\n";
+        assert_eq!(
+            eval(&format!("{prelude}let n = 5\nadd10(1)")),
+            Object::Number(11.0)
+        );
+        assert_eq!(
+            eval(&format!(
+                "{prelude}fn caller() => {{\n    let n = 100\n    ret add10(1)\n}}\ncaller()"
+            )),
+            Object::Number(11.0)
+        );
+        // A parameter of the closure still shadows what it captured
+        assert_eq!(
+            eval("fn make(n) => fn(n) => n * 2\nlet f = make(10)\nf(3)"),
+            Object::Number(6.0)
+        );
+    }
+
+    #[test]
+    fn nested_fns_share_live_locals() {
+        let counter = "fn outer() => {\n    let count = 0\n    fn inc() => { count += 1 }\n    inc()\n    inc()\n    ret count\n}\nouter()";
+        assert_eq!(eval(counter), Object::Number(2.0));
+        let push = "fn fill() => {\n    let items = []\n    fn add(x) => items.push(x)\n    add(1)\n    ret items\n}\nfill()";
+        assert_eq!(eval(push), numbers(&[1.0]));
+    }
+
+    #[test]
+    fn map_filter_reduce_need_a_function() {
+        assert!(is_error(&eval("[1].map(5)")));
+        assert!(is_error(&eval("[1].filter(\"x\")")));
+        assert!(is_error(&eval("[1].reduce(null, 0)")));
+    }
+
+    #[test]
+    fn map_filter_reduce_propagate_callback_errors() {
+        // A list literal right after the fn would be parsed as indexing its body
+        let fail = "fn fail(x) => math.floor_div(x, 0)\nlet xs = [1, 2]\n";
+        assert!(is_error(&eval(&format!("{fail}xs.map(fail)"))));
+        assert!(is_error(&eval(&format!("{fail}xs.filter(fail)"))));
+        assert!(is_error(&eval(&format!(
+            "{fail}xs.reduce(fn(acc, x) => fail(x), 0)"
+        ))));
+    }
+
+    #[test]
+    fn sort_survives_an_inconsistent_comparator() {
+        let src = "let xs = []\nfor i -> 0..200 { xs.push(i) }\nxs.sort(fn(a, b) => random_int(-1, 1)).len()";
+        assert_eq!(eval(src), Object::Number(200.0));
+    }
+
+    #[test]
+    fn sort_returns_comparator_errors() {
+        assert!(is_error(&eval(
+            "[2, 1].sort(fn(a, b) => math.floor_div(a, 0))"
+        )));
+        assert!(is_error(&eval("[2, 1].sort(fn(a, b) => \"x\")")));
+        assert!(is_error(&eval("[2, \"a\"].sort()")));
+    }
+
+    #[test]
+    fn call_depth_limit_is_a_catchable_error() {
+        // Debug frames are large, test threads get too small a stack even for
+        // a low limit
+        let result = std::thread::Builder::new()
+            .stack_size(1 << 28)
+            .spawn(|| {
+                let mut vm = Interpreter::new(".".to_string(), None);
+                vm.max_call_depth = 100;
+                let caught = vm.eval_str(
+                    "fn f(n) => f(n + 1)\nlet r = try { f(0) } catch e { \"caught\" }\nr",
+                );
+                let caught = caught == Object::String(Box::new("caught".to_string()));
+                (caught, is_error(&vm.eval_str("f(0)")))
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(result, (true, true));
+    }
+
+    #[test]
+    fn queue_methods_work_on_temporaries() {
+        assert_eq!(eval("[1].enqueue(2)"), Object::Null);
+        assert_eq!(eval("[1, 2].dequeue()"), Object::Number(1.0));
+        assert!(is_error(&eval("[].dequeue()")));
+        assert_eq!(eval("[1, 2].pop()"), Object::Number(2.0));
+        assert_eq!(
+            eval("let q = [1]\nq.enqueue(2)\nq.dequeue()\nq"),
+            numbers(&[2.0])
+        );
+        assert!(is_error(&eval("let q = [1]\nq.enqueue(1 / 0)")));
+        assert_eq!(
+            eval("let q = [1]\ntry { q.enqueue(1 / 0) } catch e { 0 }\nq"),
+            numbers(&[1.0])
+        );
+    }
+
+    #[test]
+    fn prepend_returns_an_error_argument() {
+        assert_eq!(eval("[2, 3].prepend(1)"), numbers(&[1.0, 2.0, 3.0]));
+        assert!(is_error(&eval("[2, 3].prepend(1 / 0)")));
+    }
+
+    #[test]
+    fn split_and_join_return_an_error_separator() {
+        assert!(is_error(&eval("\"a,b\".split(1 / 0)")));
+        assert!(is_error(&eval("[\"a\", \"b\"].join(1 / 0)")));
+        assert_eq!(
+            eval("\"a,b\".split(\",\").join(\"-\")"),
+            Object::String(Box::new("a-b".to_string()))
+        );
+    }
+
+    #[test]
+    fn instances_round_trip_through_json_methods() {
+        let prelude = "struct P { let x = 0\nlet tags = [] }\nlet p = P { x: 1, tags: [\"a\"] }\n";
+        assert_eq!(
+            eval(&format!("{prelude}p.to_json()")),
+            Object::String(Box::new(r#"{"__type":"P","tags":["a"],"x":1}"#.to_string()))
+        );
+        assert_eq!(
+            eval(&format!("{prelude}P::from_json(p.to_json()) == p")),
+            Object::Bool(true)
+        );
+        assert!(is_error(&eval(&format!("{prelude}P::from_json(5)"))));
+    }
+
+    #[test]
+    fn let_destructures_lists() {
+        assert_eq!(
+            eval("let [a, b] = [1, 2]\nlet r = [b, a]\nr"),
+            numbers(&[2.0, 1.0])
+        );
+        // Missing items are null and extra ones are dropped
+        assert_eq!(eval("let [a, b] = [1]\nb"), Object::Null);
+        assert_eq!(eval("let [a] = [1, 2]\na"), Object::Number(1.0));
+        assert!(is_error(&eval("let [a, b] = 5")));
+    }
+
+    #[test]
+    fn field_assignment_without_a_target_is_an_error() {
+        let prelude = "struct P { let x = 1 }\nfn mk() => P { x: 1 }\nlet p = mk()\n";
+        assert!(is_error(&eval(&format!("{prelude}mk().x = 5"))));
+        assert!(is_error(&eval(&format!("{prelude}p.y = 5"))));
+        assert_eq!(eval(&format!("{prelude}p.x = 5\np.x")), Object::Number(5.0));
+    }
+
+    #[test]
+    fn and_or_skip_the_right_side_when_decided() {
+        let prelude = "let hits = 0\nfn bump() => {\n    hits += 1\n    ret true\n}\n";
+        assert_eq!(
+            eval(&format!("{prelude}false && bump()\ntrue || bump()\nhits")),
+            Object::Number(0.0)
+        );
+        assert_eq!(
+            eval(&format!("{prelude}true && bump()\nfalse || bump()\nhits")),
+            Object::Number(2.0)
+        );
+        assert_eq!(eval("0 && 1"), Object::Bool(false));
+        assert_eq!(eval("1 || 0"), Object::Bool(true));
+    }
+
+    #[test]
+    fn scaling_a_range_scales_its_step() {
+        let collect =
+            |range: &str| eval(&format!("let r = []\nfor i -> {range} {{ r.push(i) }}\nr"));
+        assert_eq!(
+            collect("(0..10 step 2) * 3"),
+            numbers(&[0.0, 6.0, 12.0, 18.0, 24.0])
+        );
+        assert_eq!(collect("(1..4) * 2"), numbers(&[2.0, 4.0, 6.0]));
+        assert_eq!(collect("-1 * (0..3)"), numbers(&[0.0, -1.0, -2.0]));
+        assert!(is_error(&eval("(0..10) * 0")));
+        assert!(is_error(&eval("(0..10) * 0.5")));
+    }
+
+    #[test]
+    fn ranges_slice_lists_and_strings() {
+        let text = |s: &str| Object::String(Box::new(s.to_string()));
+        assert_eq!(eval("[1, 2, 3, 4][1..3]"), numbers(&[2.0, 3.0]));
+        assert_eq!(eval("\"hello\"[1..3]"), text("el"));
+        // Out of bounds ends are clamped
+        assert_eq!(eval("[1, 2, 3][2..10]"), numbers(&[3.0]));
+        assert_eq!(eval("[1, 2][5..9]"), numbers(&[]));
+        assert_eq!(eval("\"hello\"[3..99]"), text("lo"));
+        // Reversed and empty ranges give nothing
+        assert_eq!(eval("[1, 2, 3][3..1]"), numbers(&[]));
+        assert_eq!(eval("\"abc\"[2..2]"), text(""));
+    }
+
+    #[test]
+    fn caught_errors_expose_fields_with_dot() {
+        let src = "let m = \"\"\ntry { 10 / 0 } catch e { m = e.msg }\nm";
+        let Object::String(msg) = eval(src) else {
+            panic!("expected the message");
+        };
+        assert!(msg.contains("Division by zero"), "{msg}");
+        assert_eq!(
+            eval("let l = 0\ntry { 1 / 0 } catch e { l = e.line }\nl"),
+            Object::Number(2.0)
+        );
+        assert_eq!(eval("{\"a\": 1}.b"), Object::Null);
+    }
+
+    #[test]
+    fn repetition_past_the_cap_is_an_error() {
+        assert!(is_error(&eval("\"a\" * math.pow(10, 18)")));
+        assert!(is_error(&eval("math.pow(10, 18) * \"ab\"")));
+        assert!(is_error(&eval("[1, 2] * math.pow(10, 18)")));
+        assert_eq!(
+            eval("\"ab\" * 3"),
+            Object::String(Box::new("ababab".to_string()))
+        );
+        assert_eq!(eval("[1] * 2"), numbers(&[1.0, 1.0]));
+        assert_eq!(eval("[1] * -1"), numbers(&[]));
+    }
+
+    #[test]
+    fn division_by_zero_names_the_operation() {
+        let Object::Error { msg, .. } = eval("10 / 0") else {
+            panic!("expected an error");
+        };
+        assert!(msg.contains("Division by zero in 10 / 0"), "{msg}");
+        let caught = eval("let x = 0\ntry { 1 / x } catch e { \"caught\" }");
+        assert_eq!(caught, Object::String(Box::new("caught".to_string())));
+    }
+
+    #[test]
+    fn for_loops_count_up_and_down() {
+        let collect =
+            |range: &str| eval(&format!("let r = []\nfor i -> {range} {{ r.push(i) }}\nr"));
+        assert_eq!(collect("0..4"), numbers(&[0.0, 1.0, 2.0, 3.0]));
+        assert_eq!(collect("4..0"), numbers(&[4.0, 3.0, 2.0, 1.0]));
+        assert_eq!(collect("3..3"), numbers(&[]));
+        // Float ends are truncated toward zero
+        assert_eq!(collect("0.9..2.5"), numbers(&[0.0, 1.0]));
+    }
+
+    #[test]
+    fn for_loops_over_bad_ranges_are_errors() {
+        for src in [
+            "for i -> 0..10000000000000000000000 { }",
+            "for i -> \"a\"..\"b\" { }",
+            "for i -> 5 { }",
+        ] {
+            assert!(is_error(&eval(src)), "{src}");
+            assert_eq!(
+                eval(&format!("let r = try {{ {src} }} catch e {{ 0 }}\nr")),
+                Object::Number(0.0),
+                "{src}"
+            );
+        }
+    }
+
+    fn import_path(vm: &Interpreter, path: &str) -> PathBuf {
+        vm.resolve_import_path(&Tree::String(Box::new(path.to_string())))
+            .unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn import_paths_join_with_the_unix_separator() {
+        let vm = Interpreter::new(
+            "/home/me/proj".to_string(),
+            Some("/opt/iok/std".to_string()),
+        );
+        assert_eq!(
+            import_path(&vm, "lib/foo.iok"),
+            Path::new("/home/me/proj/lib/foo.iok")
+        );
+        assert_eq!(vm.std_lib_path("io"), Path::new("/opt/iok/std/io.iok"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn import_paths_join_with_the_windows_separator() {
+        let vm = Interpreter::new(r"C:\proj".to_string(), Some(r"C:\iok\std".to_string()));
+        let path = import_path(&vm, "lib/foo.iok");
+        assert_eq!(path, Path::new(r"C:\proj\lib\foo.iok"));
+        assert_eq!(vm.std_lib_path("io"), Path::new(r"C:\iok\std\io.iok"));
+    }
+
+    #[test]
+    fn mutual_imports_report_the_cycle() {
+        let dir = env::temp_dir().join(format!("iok-cycle-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.iok"), "import \"b.iok\"\nlet a = 1\n").unwrap();
+        std::fs::write(dir.join("b.iok"), "import \"a.iok\"\nlet b = 2\n").unwrap();
+        let dir_name = dir.to_string_lossy().to_string();
+
+        let a = dir.join("a.iok").canonicalize().unwrap();
+        let b = dir.join("b.iok").canonicalize().unwrap();
+        let cycle = format!(
+            "Circular import: {} -> {} -> {}",
+            a.display(),
+            b.display(),
+            a.display()
+        );
+
+        // The error comes back out of every import on the way, and nothing
+        // that failed is cached
+        let mut vm = Interpreter::new(dir_name.clone(), None);
+        let Object::Error { msg, .. } = vm.eval_str("import \"a.iok\"") else {
+            panic!("expected a circular import error");
+        };
+        assert_eq!(*msg, cycle);
+        assert!(vm.modules.is_empty());
+        assert_eq!(
+            vm.eval_str("try { import \"a.iok\" } catch e { \"caught\" }"),
+            Object::String(Box::new("caught".to_string()))
+        );
+
+        // Run as the entry file, `a` is already loading when `b` imports it
+        let mut vm = Interpreter::new(dir_name, None);
+        vm.set_entry_file(&a);
+        let Object::Error { msg, .. } = vm.eval_str("import \"b.iok\"") else {
+            panic!("expected a circular import error");
+        };
+        assert_eq!(*msg, cycle);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_imports_are_errors() {
+        let dir = env::temp_dir().join(format!("iok-unreadable-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("bad.iok"), [0xff, 0xfe, 0x00]).unwrap();
+        let mut vm = Interpreter::new(dir.to_string_lossy().to_string(), None);
+        for src in [
+            "import \"missing.iok\"",
+            "import \"sub\"",
+            "import \"bad.iok\"",
+            "import 5::a",
+        ] {
+            assert!(is_error(&vm.eval_str(src)), "{src}");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn literals_and_elsif_conditions_return_errors() {
+        let prelude = "struct P { let x = 0 }\nfn fail() => log.set_level(\"bogus\")\n";
+        for src in [
+            "let xs = [1, fail()]",
+            "let m = {\"a\": fail()}",
+            "let p = P { x: fail() }",
+            "if false { 1 } elsif fail() { 2 } els { 3 }",
+        ] {
+            assert!(is_error(&eval(&format!("{prelude}{src}"))), "{src}");
+            assert_eq!(
+                eval(&format!(
+                    "{prelude}let r = try {{ {src} }} catch e {{ 0 }}\nr"
+                )),
+                Object::Number(0.0),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_list_and_string_methods_are_errors() {
+        let Object::Error { msg, .. } = eval("[1].nope()") else {
+            panic!("expected an error");
+        };
+        assert_eq!(*msg, "list has no method nope");
+        let Object::Error { msg, .. } = eval("\"a\".nope()") else {
+            panic!("expected an error");
+        };
+        assert_eq!(*msg, "string has no method nope");
+    }
+
+    #[test]
+    fn bad_push_pop_and_index_writes_are_errors() {
+        for src in [
+            "let l = [1]\nl.push()",
+            "let l = [1, 2]\nl[5] = 2",
+            "let l = [1, 2]\nl[-3] = 2",
+            "let l = []\nl.pop()",
+            "let s = \"\"\ns.pop()",
+            "[].dequeue()",
+            "[].peek()",
+            "[].peek_back()",
+            "[].head()",
+        ] {
+            assert!(is_error(&eval(src)), "{src}");
+        }
+        assert_eq!(eval("let l = [1, 2]\nl[-1] = 5\nl"), numbers(&[1.0, 5.0]));
+        assert!(is_error(&eval("let m = {}\nm[1 / 0] = 1")));
+        assert_eq!(
+            eval("let m = {}\ntry { m[1 / 0] = 1 } catch e { 0 }\nm.len()"),
+            Object::Number(0.0)
+        );
     }
 }
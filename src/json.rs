@@ -0,0 +1,341 @@
+// JSON conversion for `json_stringify` and `json_parse`.
+//
+// Struct instances are written as objects with an extra `"__type"` key holding
+// the struct name, e.g. `{"__type": "Point", "x": 1, "y": 2}`. When parsing,
+// objects whose `__type` names one of the given struct definitions become
+// instances again, any other object (tagged or not) stays a map.
+// Object keys are written in sorted order so the output is stable
+use crate::object::Object;
+use rustc_hash::FxHashMap;
+use std::{iter::Peekable, str::Chars};
+
+pub const TYPE_KEY: &str = "__type";
+
+pub fn stringify(obj: &Object) -> Result<String, String> {
+    let mut out = String::new();
+    write_value(obj, &mut out)?;
+    Ok(out)
+}
+
+fn write_value(obj: &Object, out: &mut String) -> Result<(), String> {
+    match obj {
+        Object::Null => out.push_str("null"),
+        Object::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Object::Number(n) if n.is_finite() => out.push_str(&n.to_string()),
+        Object::Number(n) => return Err(format!("Can't write {n} as JSON")),
+        #[cfg(feature = "bignum")]
+        Object::BigInt(n) => out.push_str(&n.to_string()),
+        Object::String(s) => write_string(s, out),
+        Object::List(list) => {
+            out.push('[');
+            for (i, item) in list.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out)?;
+            }
+            out.push(']');
+        }
+        Object::Map(map) => write_object(None, map, out)?,
+        Object::Instance { struct_def, fields } => {
+            let name = match &**struct_def {
+                Object::StructDef { name, .. } => name.to_string(),
+                _ => String::new(),
+            };
+            write_object(Some(&name), fields, out)?;
+        }
+        other => return Err(format!("Can't write a {} as JSON", other.type_name())),
+    }
+    Ok(())
+}
+
+fn write_object(
+    type_name: Option<&str>,
+    map: &FxHashMap<String, Object>,
+    out: &mut String,
+) -> Result<(), String> {
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort();
+
+    out.push('{');
+    if let Some(name) = type_name {
+        write_string(TYPE_KEY, out);
+        out.push(':');
+        write_string(name, out);
+    }
+    for (i, key) in keys.into_iter().enumerate() {
+        if i > 0 || type_name.is_some() {
+            out.push(',');
+        }
+        write_string(key, out);
+        out.push(':');
+        write_value(&map[key], out)?;
+    }
+    out.push('}');
+    Ok(())
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// `structs` are the definitions instances may be rebuilt from, by name
+pub fn parse(input: &str, structs: &FxHashMap<String, Object>) -> Result<Object, String> {
+    let mut parser = JsonParser {
+        iter: input.chars().peekable(),
+        pos: 0,
+        structs,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.iter.peek().is_some() {
+        return Err(parser.error("Unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+// `Struct::from_json(text)`. The object needs exactly the fields the struct
+// declares, a `__type` tag is optional but has to name the struct. Nested
+// objects tagged with the same struct become instances too
+pub fn parse_instance(input: &str, def: &Object) -> Result<Object, String> {
+    let Object::StructDef { name, fields, .. } = def else {
+        return Err(format!("Expected a struct found {}", def.type_name()));
+    };
+    let mut map = match parse(input, &FxHashMap::default())? {
+        Object::Map(map) => map,
+        other => {
+            return Err(format!(
+                "Expected a JSON object found {}",
+                other.type_name()
+            ))
+        }
+    };
+    match map.remove(TYPE_KEY) {
+        None => {}
+        Some(Object::String(tag)) if *tag == **name => {}
+        Some(tag) => return Err(format!("Expected a {name} found {TYPE_KEY} {tag}")),
+    }
+    let mut missing: Vec<&str> = fields
+        .keys()
+        .filter(|field| !map.contains_key(*field))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        missing.sort();
+        return Err(format!("{name} JSON is missing {}", missing.join(", ")));
+    }
+
+    let mut structs = FxHashMap::default();
+    structs.insert(name.to_string(), def.clone());
+    let values = match parse(input, &structs)? {
+        Object::Map(values) => values,
+        Object::Instance { fields, .. } => fields,
+        other => {
+            return Err(format!(
+                "Expected a JSON object found {}",
+                other.type_name()
+            ))
+        }
+    };
+    match def.instance_from(&values) {
+        Object::Error { msg, .. } => Err(*msg),
+        instance => Ok(instance),
+    }
+}
+
+struct JsonParser<'a> {
+    iter: Peekable<Chars<'a>>,
+    pos: usize,
+    structs: &'a FxHashMap<String, Object>,
+}
+
+impl JsonParser<'_> {
+    fn next(&mut self) -> Option<char> {
+        self.pos += 1;
+        self.iter.next()
+    }
+
+    fn error(&self, msg: &str) -> String {
+        format!("Invalid JSON: {msg} at character {}", self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.iter.peek().is_some_and(|c| c.is_whitespace()) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("Expected {expected} found {c}"))),
+            None => Err(self.error(&format!("Expected {expected} found the end"))),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Object, String> {
+        self.skip_whitespace();
+        match self.iter.peek().copied() {
+            Some('{') => self.parse_object(),
+            Some('[') => self.parse_array(),
+            Some('"') => Ok(Object::String(Box::new(self.parse_string()?))),
+            Some('-' | '0'..='9') => self.parse_number(),
+            Some('a'..='z') => self.parse_literal(),
+            Some(c) => Err(self.error(&format!("Unexpected {c}"))),
+            None => Err(self.error("Unexpected end")),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Object, String> {
+        let mut word = String::new();
+        while let Some(c) = self.iter.peek().copied().filter(char::is_ascii_lowercase) {
+            word.push(c);
+            self.next();
+        }
+        match word.as_str() {
+            "true" => Ok(Object::Bool(true)),
+            "false" => Ok(Object::Bool(false)),
+            "null" => Ok(Object::Null),
+            _ => Err(self.error(&format!("Unknown literal {word}"))),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Object, String> {
+        let mut number = String::new();
+        while let Some(c) = self
+            .iter
+            .peek()
+            .copied()
+            .filter(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            number.push(c);
+            self.next();
+        }
+        number
+            .parse()
+            .map(Object::Number)
+            .map_err(|_| self.error(&format!("Invalid number {number}")))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("Invalid \\u escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('/') => string.push('/'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('u') => {
+                        let mut code = self.parse_hex4()?;
+                        // Characters past the BMP are written as a surrogate pair
+                        if (0xD800..0xDC00).contains(&code) {
+                            if self.next() != Some('\\') || self.next() != Some('u') {
+                                return Err(self.error("Unpaired surrogate"));
+                            }
+                            let low = self.parse_hex4()?;
+                            if !(0xDC00..0xE000).contains(&low) {
+                                return Err(self.error("Unpaired surrogate"));
+                            }
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                        }
+                        string.push(
+                            char::from_u32(code).ok_or_else(|| self.error("Invalid \\u escape"))?,
+                        );
+                    }
+                    _ => return Err(self.error("Invalid escape")),
+                },
+                Some(c) => string.push(c),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Object, String> {
+        self.expect('[')?;
+        let mut list = vec![];
+        self.skip_whitespace();
+        if self.iter.peek() == Some(&']') {
+            self.next();
+            return Ok(Object::List(list));
+        }
+        loop {
+            list.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Object::List(list)),
+                _ => return Err(self.error("Expected , or ]")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Object, String> {
+        self.expect('{')?;
+        let mut map = FxHashMap::default();
+        self.skip_whitespace();
+        if self.iter.peek() == Some(&'}') {
+            self.next();
+            return Ok(Object::Map(map));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            map.insert(key, value);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => break,
+                _ => return Err(self.error("Expected , or }")),
+            }
+        }
+
+        let def = match map.get(TYPE_KEY) {
+            Some(Object::String(name)) => self.structs.get(&**name),
+            _ => None,
+        };
+        match def {
+            Some(def) => {
+                map.remove(TYPE_KEY);
+                match def.instance_from(&map) {
+                    Object::Error { msg, .. } => Err(*msg),
+                    instance => Ok(instance),
+                }
+            }
+            None => Ok(Object::Map(map)),
+        }
+    }
+}
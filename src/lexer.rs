@@ -20,15 +20,30 @@ lazy_static! {
         map.insert("false", TokenType::Bool(false));
         map.insert("null", TokenType::Null);
         map.insert("import", TokenType::Import);
+        map.insert("try", TokenType::Try);
+        map.insert("catch", TokenType::Catch);
         map
     };
 }
 
+// Pieces of a string literal with `{expr}` in it
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    Expr(Vec<Token>),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     Number(f64),
+    // An integer literal past 2^53, kept exact
+    #[cfg(feature = "bignum")]
+    BigInt(crate::bigint::BigInt),
     Bool(bool),
     String(String),
+    // `r"..."`, backslashes and braces are kept as written
+    RawString(String),
+    InterpString(Vec<StringPart>),
     Null,
     Plus,
     PlusEqu,
@@ -48,7 +63,9 @@ pub enum TokenType {
     BitAnd,
     And,
     BitOR,
+    BitXor,
     Or,
+    Pipe,
     Shl,
     Shr,
     OpenParen,
@@ -63,6 +80,8 @@ pub enum TokenType {
     DColon,
     Dot,
     DDot,
+    // `...` before a rest parameter
+    Ellipsis,
     ThinArrow,
     FatArrow,
     Let,
@@ -77,15 +96,17 @@ pub enum TokenType {
     Struct,
     Import,
     As,
+    Try,
+    Catch,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Loc {
     pub x: usize,
     pub y: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token: TokenType,
     pub loc: Loc,
@@ -95,21 +116,38 @@ pub struct Token {
 pub struct Lexer<'a> {
     curr_loc: Loc,
     pub iter: Peekable<Chars<'a>>,
+    logger: Logger,
 }
 impl<'a> Lexer<'a> {
-    pub fn new(input: &'a String) -> Lexer<'a> {
+    pub fn new(input: &'a str) -> Lexer<'a> {
         let iter = input.chars().peekable();
         Lexer {
             curr_loc: Loc { x: 1, y: 1 },
             iter,
+            logger: Logger::with_source(input),
         }
     }
 
+    // Lexing errors reported so far, tokens are still returned after one
+    pub fn error_count(&self) -> usize {
+        self.logger.error_count()
+    }
+
     pub fn tokenize(&mut self) -> Vec<Token> {
         let mut tokens: Vec<Token> = Vec::new();
 
         while let Some(&c) = self.iter.peek() {
+            // Tokens point at their first character
+            let start = self.curr_loc;
             match c {
+                'r' if matches!(self.iter.clone().nth(1), Some('\"' | '#')) => {
+                    if let Some(string) = self.raw_string() {
+                        tokens.push(Token {
+                            token: TokenType::RawString(string),
+                            loc: start,
+                        });
+                    }
+                }
                 'a'..='z' | '_' | 'A'..='Z' => {
                     let mut buf = String::new();
                     while let Some(&c) = self.iter.peek() {
@@ -123,367 +161,448 @@ impl<'a> Lexer<'a> {
                     if let Some(token_type) = KEYWORDS.get(buf.as_str()) {
                         tokens.push(Token {
                             token: token_type.clone(),
-                            loc: self.curr_loc,
+                            loc: start,
                         });
                     } else {
                         tokens.push(Token {
                             token: TokenType::Ident(buf),
-                            loc: self.curr_loc,
+                            loc: start,
                         });
                     }
                 }
                 '0'..='9' => {
                     let mut number = String::new();
                     while let Some(&c) = self.iter.peek() {
-                        if c.is_digit(10) || (c == '.' && self.iter.clone().nth(1) != Some('.')) {
+                        if c.is_ascii_digit() || (c == '.' && self.iter.clone().nth(1) != Some('.'))
+                        {
                             number.push(c);
                             self.next();
                         } else {
                             break;
                         }
                     }
-                    let num = number.parse().unwrap();
-                    tokens.push(Token {
-                        token: TokenType::Number(num),
-                        loc: self.curr_loc,
-                    });
+                    #[cfg(feature = "bignum")]
+                    if let Some(big) =
+                        crate::bigint::BigInt::parse(&number).filter(|big| !big.fits_f64())
+                    {
+                        tokens.push(Token {
+                            token: TokenType::BigInt(big),
+                            loc: start,
+                        });
+                        continue;
+                    }
+                    match number.parse() {
+                        Ok(num) => tokens.push(Token {
+                            token: TokenType::Number(num),
+                            loc: start,
+                        }),
+                        Err(_) => self.logger.error(
+                            &format!("Invalid number {number}"),
+                            start,
+                            ErrorType::Lexing,
+                        ),
+                    }
                 }
                 '\"' => {
-                    self.next();
+                    // `"""..."""` can hold newlines and lone quotes, a newline right
+                    // after the opening quotes isn't part of the string
+                    let triple = self.iter.clone().take(3).eq("\"\"\"".chars());
+                    let quotes = if triple { 3 } else { 1 };
+                    for _ in 0..quotes {
+                        self.next();
+                    }
+                    if triple && self.iter.peek() == Some(&'\n') {
+                        self.next();
+                    }
                     let mut string = String::new();
+                    let mut parts = vec![];
+                    let mut closed = false;
                     while let Some(&c) = self.iter.peek() {
                         match c {
-                            '\"' => {
-                                self.next();
+                            '\"' if !triple || self.iter.clone().take(3).eq("\"\"\"".chars()) => {
+                                for _ in 0..quotes {
+                                    self.next();
+                                }
+                                closed = true;
                                 break;
                             }
+                            // Escapes are kept for the parser, only the ones that
+                            // would end the string or start `{expr}` matter here.
+                            // `\${` is a literal `${`
                             '\\' => {
                                 string.push(c);
                                 self.next();
-                                if *self.iter.peek().unwrap() == '\"' {
-                                    string.push('\"');
+                                if let Some(&c @ ('\"' | '{' | '}' | '$')) = self.iter.peek() {
+                                    string.push(c);
                                     self.next();
+                                    if c == '$' && self.iter.peek() == Some(&'{') {
+                                        string.push_str("\\{");
+                                        self.next();
+                                    }
+                                }
+                            }
+                            // `${expr}` is the same as `{expr}`
+                            '$' if self.iter.clone().nth(1) == Some('{') => {
+                                self.next();
+                                match self.interpolation() {
+                                    Some(expr) => {
+                                        parts.push(StringPart::Text(std::mem::take(&mut string)));
+                                        parts.push(StringPart::Expr(expr));
+                                    }
+                                    None => string.push(c),
                                 }
                             }
+                            // `{{` is text, kept doubled so `format` can tell it
+                            // from a placeholder
+                            '{' if self.iter.clone().nth(1) == Some('{') => {
+                                string.push_str("{{");
+                                self.next();
+                                self.next();
+                            }
+                            '{' => match self.interpolation() {
+                                Some(expr) => {
+                                    parts.push(StringPart::Text(std::mem::take(&mut string)));
+                                    parts.push(StringPart::Expr(expr));
+                                }
+                                None => {
+                                    string.push(c);
+                                    self.next();
+                                }
+                            },
                             _ => {
                                 string.push(c);
                                 self.next();
                             }
                         }
                     }
-                    tokens.push(Token {
-                        token: TokenType::String(string),
-                        loc: self.curr_loc,
-                    });
+                    if !closed {
+                        self.logger
+                            .error("Unterminated string", start, ErrorType::Lexing);
+                    }
+                    let token = if parts.is_empty() {
+                        TokenType::String(string)
+                    } else {
+                        parts.push(StringPart::Text(string));
+                        TokenType::InterpString(parts)
+                    };
+                    tokens.push(Token { token, loc: start });
                 }
                 '(' => {
                     tokens.push(Token {
                         token: TokenType::OpenParen,
-                        loc: self.curr_loc,
+                        loc: start,
                     });
                     self.next();
                 }
                 ')' => {
                     tokens.push(Token {
                         token: TokenType::CloseParen,
-                        loc: self.curr_loc,
+                        loc: start,
                     });
                     self.next();
                 }
                 '[' => {
                     tokens.push(Token {
                         token: TokenType::OpenSquare,
-                        loc: self.curr_loc,
+                        loc: start,
                     });
                     self.next();
                 }
                 ']' => {
                     tokens.push(Token {
                         token: TokenType::CloseSquare,
-                        loc: self.curr_loc,
+                        loc: start,
                     });
                     self.next();
                 }
                 '{' => {
                     tokens.push(Token {
                         token: TokenType::OpenCurly,
-                        loc: self.curr_loc,
+                        loc: start,
                     });
                     self.next();
                 }
                 '}' => {
                     tokens.push(Token {
                         token: TokenType::CloseCurly,
-                        loc: self.curr_loc,
+                        loc: start,
                     });
                     self.next();
                 }
                 '+' => {
                     self.next();
-                    if let Some(c) = self.iter.peek() {
-                        match c {
-                            '+' => {
-                                tokens.push(Token {
-                                    token: TokenType::DPlus,
-                                    loc: self.curr_loc,
-                                });
-                                self.next();
-                            }
-                            '=' => {
-                                tokens.push(Token {
-                                    token: TokenType::PlusEqu,
-                                    loc: self.curr_loc,
-                                });
-                                self.next();
-                            }
-                            _ => {
-                                tokens.push(Token {
-                                    token: TokenType::Plus,
-                                    loc: self.curr_loc,
-                                });
-                            }
+                    match self.iter.peek() {
+                        None => self.eof_error("+"),
+                        Some('+') => {
+                            self.next();
+                            tokens.push(Token {
+                                token: TokenType::DPlus,
+                                loc: start,
+                            });
                         }
+                        Some('=') => {
+                            self.next();
+                            tokens.push(Token {
+                                token: TokenType::PlusEqu,
+                                loc: start,
+                            });
+                        }
+                        _ => tokens.push(Token {
+                            token: TokenType::Plus,
+                            loc: start,
+                        }),
                     }
                 }
                 '-' => {
                     self.next();
-                    match *self.iter.peek().unwrap() {
-                        '-' => {
+                    match self.iter.peek() {
+                        None => self.eof_error("-"),
+                        Some('-') => {
                             self.next();
                             tokens.push(Token {
                                 token: TokenType::DMinus,
-                                loc: self.curr_loc,
+                                loc: start,
                             });
                         }
-                        '>' => {
+                        Some('>') => {
                             self.next();
                             tokens.push(Token {
                                 token: TokenType::ThinArrow,
-                                loc: self.curr_loc,
+                                loc: start,
                             });
                         }
                         _ => tokens.push(Token {
                             token: TokenType::Minus,
-                            loc: self.curr_loc,
+                            loc: start,
                         }),
                     }
                 }
                 '*' => {
                     tokens.push(Token {
                         token: TokenType::Multiply,
-                        loc: self.curr_loc,
+                        loc: start,
                     });
                     self.next();
                 }
                 '/' => {
                     self.next();
-                    if *self.iter.peek().unwrap() == '/' {
-                        while *self.iter.peek().unwrap() != '\n' {
-                            self.next();
+                    match self.iter.peek() {
+                        None => self.eof_error("/"),
+                        // A comment may end the file without a newline
+                        Some('/') => {
+                            while self.iter.peek().is_some_and(|c| *c != '\n') {
+                                self.next();
+                            }
                         }
-                    } else {
-                        tokens.push(Token {
+                        Some(_) => tokens.push(Token {
                             token: TokenType::Divide,
-                            loc: self.curr_loc,
-                        });
+                            loc: start,
+                        }),
                     }
                 }
                 '=' => {
                     self.next();
-                    if let Some(c) = self.iter.peek() {
-                        match c {
-                            '=' => {
-                                tokens.push(Token {
-                                    token: TokenType::EquEqu,
-                                    loc: self.curr_loc,
-                                });
-                                self.next();
-                            }
-                            '>' => {
-                                tokens.push(Token {
-                                    token: TokenType::FatArrow,
-                                    loc: self.curr_loc,
-                                });
-                                self.next();
-                            }
-                            _ => {
-                                tokens.push(Token {
-                                    token: TokenType::Equal,
-                                    loc: self.curr_loc,
-                                });
-                            }
+                    match self.iter.peek() {
+                        None => self.eof_error("="),
+                        Some('=') => {
+                            self.next();
+                            tokens.push(Token {
+                                token: TokenType::EquEqu,
+                                loc: start,
+                            });
                         }
+                        Some('>') => {
+                            self.next();
+                            tokens.push(Token {
+                                token: TokenType::FatArrow,
+                                loc: start,
+                            });
+                        }
+                        _ => tokens.push(Token {
+                            token: TokenType::Equal,
+                            loc: start,
+                        }),
                     }
                 }
                 '!' => {
                     self.next();
-                    if *self.iter.peek().unwrap_or(&' ') == '=' {
-                        tokens.push(Token {
-                            token: TokenType::NotEqu,
-                            loc: self.curr_loc,
-                        });
-                        self.next();
-                    } else {
-                        tokens.push(Token {
+                    match self.iter.peek() {
+                        None => self.eof_error("!"),
+                        Some('=') => {
+                            self.next();
+                            tokens.push(Token {
+                                token: TokenType::NotEqu,
+                                loc: start,
+                            });
+                        }
+                        _ => tokens.push(Token {
                             token: TokenType::Bang,
-                            loc: self.curr_loc,
-                        });
+                            loc: start,
+                        }),
                     }
                 }
                 '>' => {
                     self.next();
-                    if let Some(c) = self.iter.peek() {
-                        match c {
-                            '=' => {
-                                tokens.push(Token {
-                                    token: TokenType::GreatEqu,
-                                    loc: self.curr_loc,
-                                });
-                                self.next();
-                            }
-                            '>' => {
-                                tokens.push(Token {
-                                    token: TokenType::Shr,
-                                    loc: self.curr_loc,
-                                });
-                                self.next();
-                            }
-                            _ => {
-                                tokens.push(Token {
-                                    token: TokenType::Greater,
-                                    loc: self.curr_loc,
-                                });
-                            }
+                    match self.iter.peek() {
+                        None => self.eof_error(">"),
+                        Some('=') => {
+                            self.next();
+                            tokens.push(Token {
+                                token: TokenType::GreatEqu,
+                                loc: start,
+                            });
                         }
+                        Some('>') => {
+                            self.next();
+                            tokens.push(Token {
+                                token: TokenType::Shr,
+                                loc: start,
+                            });
+                        }
+                        _ => tokens.push(Token {
+                            token: TokenType::Greater,
+                            loc: start,
+                        }),
                     }
                 }
 
                 '<' => {
                     self.next();
-                    if let Some(c) = self.iter.peek() {
-                        match c {
-                            '=' => {
-                                tokens.push(Token {
-                                    token: TokenType::LessEqu,
-                                    loc: self.curr_loc,
-                                });
-                                self.next();
-                            }
-                            '<' => {
-                                tokens.push(Token {
-                                    token: TokenType::Shl,
-                                    loc: self.curr_loc,
-                                });
-                                self.next();
-                            }
-                            _ => {
-                                tokens.push(Token {
-                                    token: TokenType::Less,
-                                    loc: self.curr_loc,
-                                });
-                            }
+                    match self.iter.peek() {
+                        None => self.eof_error("<"),
+                        Some('=') => {
+                            self.next();
+                            tokens.push(Token {
+                                token: TokenType::LessEqu,
+                                loc: start,
+                            });
+                        }
+                        Some('<') => {
+                            self.next();
+                            tokens.push(Token {
+                                token: TokenType::Shl,
+                                loc: start,
+                            });
                         }
+                        _ => tokens.push(Token {
+                            token: TokenType::Less,
+                            loc: start,
+                        }),
                     }
                 }
                 '&' => {
                     self.next();
-                    if let Some(c) = self.iter.peek() {
-                        if *c == '&' {
+                    match self.iter.peek() {
+                        None => self.eof_error("&"),
+                        Some('&') => {
+                            self.next();
                             tokens.push(Token {
                                 token: TokenType::And,
-                                loc: self.curr_loc,
+                                loc: start,
                             });
-                            self.next();
-                        } else {
-                            tokens.push(Token {
-                                token: TokenType::BitAnd,
-                                loc: self.curr_loc,
-                            })
                         }
+                        _ => tokens.push(Token {
+                            token: TokenType::BitAnd,
+                            loc: start,
+                        }),
                     }
                 }
                 '|' => {
                     self.next();
-                    if let Some(c) = self.iter.peek() {
-                        if *c == '|' {
+                    match self.iter.peek() {
+                        None => self.eof_error("|"),
+                        Some('|') => {
+                            self.next();
                             tokens.push(Token {
                                 token: TokenType::Or,
-                                loc: self.curr_loc,
+                                loc: start,
                             });
+                        }
+                        Some('>') => {
                             self.next();
-                        } else {
                             tokens.push(Token {
-                                token: TokenType::BitOR,
-                                loc: self.curr_loc,
+                                token: TokenType::Pipe,
+                                loc: start,
                             });
                         }
+                        _ => tokens.push(Token {
+                            token: TokenType::BitOR,
+                            loc: start,
+                        }),
                     }
                 }
+                '^' => {
+                    tokens.push(Token {
+                        token: TokenType::BitXor,
+                        loc: start,
+                    });
+                    self.next();
+                }
                 '.' => {
                     self.next();
-                    if *self.iter.peek().unwrap_or(&' ') == '.' {
-                        tokens.push(Token {
-                            token: TokenType::DDot,
-                            loc: self.curr_loc,
-                        });
-                        self.next();
-                    } else {
-                        tokens.push(Token {
+                    match self.iter.peek() {
+                        None => self.eof_error("."),
+                        Some('.') => {
+                            self.next();
+                            let token = if self.iter.peek() == Some(&'.') {
+                                self.next();
+                                TokenType::Ellipsis
+                            } else {
+                                TokenType::DDot
+                            };
+                            tokens.push(Token { token, loc: start });
+                        }
+                        Some(_) => tokens.push(Token {
                             token: TokenType::Dot,
-                            loc: self.curr_loc,
-                        });
+                            loc: start,
+                        }),
                     }
                 }
                 ',' => {
                     self.next();
                     tokens.push(Token {
                         token: TokenType::Comma,
-                        loc: self.curr_loc,
+                        loc: start,
                     });
                 }
                 ';' => {
                     tokens.push(Token {
                         token: TokenType::Semi,
-                        loc: self.curr_loc,
+                        loc: start,
                     });
                     self.next();
                 }
                 ':' => {
                     self.next();
-                    if let Some(c) = self.iter.peek() {
-                        if *c == ':' {
+                    match self.iter.peek() {
+                        None => self.eof_error(":"),
+                        Some(':') => {
+                            self.next();
                             tokens.push(Token {
                                 token: TokenType::DColon,
-                                loc: self.curr_loc,
+                                loc: start,
                             });
-                            self.next();
-                        } else {
-                            tokens.push(Token {
-                                token: TokenType::Colon,
-                                loc: self.curr_loc,
-                            })
                         }
+                        _ => tokens.push(Token {
+                            token: TokenType::Colon,
+                            loc: start,
+                        }),
                     }
                 }
 
                 '@' => {
                     tokens.push(Token {
                         token: TokenType::As,
-                        loc: self.curr_loc,
+                        loc: start,
                     });
                     self.next();
                 }
 
-                '\n' => {
-                    self.curr_loc.x = 1;
-                    self.curr_loc.y += 1;
-                    self.iter.next();
-                }
-                ' ' | '\t' | '\r' => {
+                ' ' | '\t' | '\r' | '\n' => {
                     self.next();
                 }
                 _ => {
-                    Logger::error(
+                    self.logger.error(
                         &format!("Unexpected Token: {c}"),
                         self.curr_loc,
                         ErrorType::Lexing,
@@ -495,8 +614,167 @@ impl<'a> Lexer<'a> {
 
         tokens
     }
+    // Consumes a char, keeping `curr_loc` right across newlines inside tokens
+    // like multi-line strings
     fn next(&mut self) {
-        self.curr_loc.x += 1;
-        self.iter.next();
+        if self.iter.next() == Some('\n') {
+            self.curr_loc.x = 1;
+            self.curr_loc.y += 1;
+        } else {
+            self.curr_loc.x += 1;
+        }
+    }
+
+    // Tokens of the `{expr}` starting at the next char, consumed through the
+    // `}`. The expression can't hold braces, quotes or newlines, a `{` that
+    // doesn't start one like `"{"` or `"{}"` is just text
+    fn interpolation(&mut self) -> Option<Vec<Token>> {
+        let mut expr = String::new();
+        let mut closed = false;
+        for c in self.iter.clone().skip(1) {
+            match c {
+                '}' => {
+                    closed = true;
+                    break;
+                }
+                '{' | '"' | '\n' => break,
+                c => expr.push(c),
+            }
+        }
+        if !closed || expr.trim().is_empty() {
+            return None;
+        }
+
+        self.next();
+        let mut lexer = Lexer::new(&expr);
+        lexer.curr_loc = self.curr_loc;
+        lexer.logger = self.logger.clone();
+        let errors = self.logger.error_count();
+        let mut tokens = lexer.tokenize();
+        for _ in 0..=expr.chars().count() {
+            self.next();
+        }
+        // An expression that failed to lex, like `{x +}`, is left empty so
+        // the parser doesn't run what is left of it
+        if self.logger.error_count() != errors {
+            tokens.clear();
+        }
+        Some(tokens)
+    }
+
+    // `r"..."` or `r#"..."#`, the string ends at a quote followed by as many
+    // `#` as the opening one had, so `r#"say "hi""#` can hold quotes
+    fn raw_string(&mut self) -> Option<String> {
+        let start = self.curr_loc;
+        self.next();
+        let mut hashes = 0;
+        while self.iter.peek() == Some(&'#') {
+            hashes += 1;
+            self.next();
+        }
+        if self.iter.peek() != Some(&'"') {
+            self.logger.error(
+                "Expected \" to start a raw string",
+                start,
+                ErrorType::Lexing,
+            );
+            return None;
+        }
+        self.next();
+
+        let closing: String = std::iter::once('"')
+            .chain("#".repeat(hashes).chars())
+            .collect();
+        let mut string = String::new();
+        while let Some(&c) = self.iter.peek() {
+            if c == '"' && self.iter.clone().take(closing.len()).eq(closing.chars()) {
+                for _ in 0..closing.len() {
+                    self.next();
+                }
+                return Some(string);
+            }
+            string.push(c);
+            self.next();
+        }
+        self.logger
+            .error("Unterminated raw string", start, ErrorType::Lexing);
+        None
+    }
+
+    fn eof_error(&self, after: &str) {
+        self.logger.error(
+            &format!("Unexpected end of input after {after}"),
+            self.curr_loc,
+            ErrorType::Lexing,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, Object};
+
+    fn tokens(src: &str) -> Vec<TokenType> {
+        Lexer::new(src)
+            .tokenize()
+            .into_iter()
+            .map(|t| t.token)
+            .collect()
+    }
+
+    fn eval(src: &str) -> Object {
+        Interpreter::new(".".to_string(), None).eval_str(src)
+    }
+
+    fn string(s: &str) -> Object {
+        Object::String(Box::new(s.to_string()))
+    }
+
+    #[test]
+    fn interpolation_splits_the_string() {
+        let tokens = tokens("\"a {x} b\"");
+        let [TokenType::InterpString(parts)] = tokens.as_slice() else {
+            panic!("expected an interpolated string");
+        };
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], StringPart::Text("a ".to_string()));
+        assert!(matches!(&parts[1], StringPart::Expr(expr)
+            if expr.len() == 1 && expr[0].token == TokenType::Ident("x".to_string())));
+        assert_eq!(parts[2], StringPart::Text(" b".to_string()));
+    }
+
+    #[test]
+    fn interpolation_evaluates_expressions() {
+        assert_eq!(
+            eval("let a = 2\n\"{a} + {a} = {a + a}\""),
+            string("2 + 2 = 4")
+        );
+        assert_eq!(eval("let n = \"x\"\n\"[{n}]{n}\""), string("[x]x"));
+    }
+
+    #[test]
+    fn braces_that_arent_interpolation_stay_text() {
+        assert_eq!(tokens("\"{}\""), [TokenType::String("{}".to_string())]);
+        assert_eq!(eval("\"\\{a} { open\""), string("{a} { open"));
+        assert_eq!(eval("r\"{1, 2}\""), string("{1, 2}"));
+        assert_eq!(eval("let x = 1\n\"{{x}}\""), string("{{x}}"));
+    }
+
+    #[test]
+    fn operators_at_end_of_input_are_lexing_errors() {
+        for src in [
+            "-", "/", ".", "1 -", "x /", "a.", "1 +", "x =", "a >", "a <", "a &", "a |", "a :", "!",
+        ] {
+            let mut lexer = Lexer::new(src);
+            lexer.tokenize();
+            assert_eq!(lexer.error_count(), 1, "{src:?}");
+        }
+        let mut lexer = Lexer::new("a += 1 || b :: c");
+        lexer.tokenize();
+        assert_eq!(lexer.error_count(), 0);
+        assert_eq!(tokens("1 -"), [TokenType::Number(1.0)]);
+        // A comment can end the file without a newline
+        assert_eq!(tokens("1 // done"), [TokenType::Number(1.0)]);
     }
 }
@@ -0,0 +1,19 @@
+// `Object` and `Tree` box their strings on purpose to keep the enums small
+#![allow(clippy::box_collection)]
+
+#[cfg(feature = "bignum")]
+pub mod bigint;
+pub mod heap;
+pub mod interpreter;
+pub mod json;
+pub mod lexer;
+pub mod logger;
+pub mod object;
+pub mod parser;
+pub mod std_native;
+
+pub use interpreter::Interpreter;
+pub use lexer::Lexer;
+pub use object::Object;
+pub use parser::{Parser, Tree};
+pub use std_native::NativeFn;
@@ -1,10 +1,15 @@
 use crate::lexer::Loc;
 use std::fmt;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
 #[derive(Debug)]
 pub enum ErrorType {
     Lexing,
     Parsing,
+    Runtime,
 }
 
 impl fmt::Display for ErrorType {
@@ -13,10 +18,62 @@ impl fmt::Display for ErrorType {
     }
 }
 
-pub struct Logger;
+// Lexing and parsing errors print the offending line with a `^` under the
+// column when the logger was made with the source text. Clones share the
+// error count, so a logger handed to a nested lexer or parser sees its errors
+#[derive(Clone, Debug, Default)]
+pub struct Logger {
+    lines: Vec<String>,
+    errors: Arc<AtomicUsize>,
+}
 
 impl Logger {
-    pub fn error(msg: &str, loc: Loc, err: ErrorType) {
+    pub fn with_source(source: &str) -> Logger {
+        Logger {
+            lines: source.lines().map(String::from).collect(),
+            errors: Arc::default(),
+        }
+    }
+
+    pub fn error(&self, msg: &str, loc: Loc, err: ErrorType) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
         eprintln!("{err} Error:\n\t{msg} at line {}:{}", loc.y, loc.x);
+        if let Some(snippet) = self.snippet(loc) {
+            eprintln!("{snippet}");
+        }
+    }
+
+    //  3 | let x = (1 + 2
+    //    |         ^
+    fn snippet(&self, loc: Loc) -> Option<String> {
+        let line = self.lines.get(loc.y.checked_sub(1)?)?;
+        let number = loc.y.to_string();
+        let gutter = " ".repeat(number.len());
+        // Tabs are copied so the caret lines up however they are displayed
+        let pad: String = line
+            .chars()
+            .take(loc.x.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        Some(format!("\t{number} | {line}\n\t{gutter} | {pad}^"))
+    }
+
+    // Runtime errors are printed without a snippet, the failing code may come
+    // from an imported file the logger doesn't have
+    pub fn runtime_error(msg: &str, loc: Option<Loc>) {
+        match loc {
+            Some(loc) => eprintln!(
+                "{} Error:\n\t{msg} at line {}:{}",
+                ErrorType::Runtime,
+                loc.y,
+                loc.x
+            ),
+            None => eprintln!("{} Error:\n\t{msg}", ErrorType::Runtime),
+        }
+    }
+
+    // Errors reported through this logger and its clones
+    pub fn error_count(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
     }
 }
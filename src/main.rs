@@ -1,39 +1,111 @@
-mod interpreter;
-mod lexer;
-mod logger;
-mod object;
-mod parser;
-mod std_native;
-
-use interpreter::Interpreter;
-use lexer::Lexer;
-use parser::Parser;
-use std::{env, fs::File, io, io::Read, io::Write, path::Path};
-fn interpret_mode(interpreter: &mut Interpreter) {
-    let mut input = String::new();
-    loop {
-        print!(">");
-        input.clear();
-        io::stdout().flush().unwrap();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read line");
+mod repl;
+
+use iok::{Interpreter, Lexer, Object, Parser};
+use repl::interpret_mode;
+use std::{
+    env, fs,
+    path::Path,
+    process, thread,
+    time::{Duration, Instant},
+};
+
+// How often `--watch` checks the script for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(300);
+// Stack of the interpreter thread, room for `MAX_CALL_DEPTH` nested calls
+// even in debug builds
+const STACK_SIZE: usize = 1 << 30;
+
+// Runs a script in a fresh interpreter, returns false if it can't be read or
+// stopped with an error. With `time` set the duration of each phase is
+// printed to stderr
+fn run_file(file_name: &str, std_path: &Option<String>, time: bool) -> bool {
+    let input = match fs::read_to_string(file_name) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("Can't open file {file_name}: {err}");
+            return false;
+        }
+    };
+
+    let start = Instant::now();
+    let mut lexer = Lexer::new(&input);
+    let tokens = lexer.tokenize();
+    let lexed = Instant::now();
 
-        input = input.trim_end().to_string();
+    let mut parser = Parser::with_source(tokens, &input);
+
+    let parsed_tree = parser.parse_tokens();
+    let parsed = Instant::now();
+    // A script with syntax errors is not run, the errors are already printed
+    if lexer.error_count() + parser.error_count() != 0 {
+        return false;
+    }
 
-        if input.is_empty() {
-            continue;
+    let dir_path = Path::new(file_name);
+    let path = if let Ok(abs_path) = dir_path.canonicalize() {
+        if let Some(parent) = abs_path.parent() {
+            parent.to_str().unwrap().to_string()
+        } else {
+            String::from("/")
+        }
+    } else {
+        String::from("/")
+    };
+
+    let mut interpreter = Interpreter::new(path, std_path.clone());
+    interpreter.set_entry_file(dir_path);
+
+    let mut ok = true;
+    for stmt in &parsed_tree {
+        let result = interpreter.interpret(stmt);
+        if let Object::Error { .. } = result {
+            interpreter.report_error(&result);
+            ok = false;
+            break;
         }
+    }
 
-        let mut lexer = Lexer::new(&input);
-        let mut parser = Parser::new(lexer.tokenize());
-        let ast = parser.parse_tokens();
-        let obj = interpreter.interpret(ast.last().unwrap());
-        println!("-> {}", obj);
+    if time {
+        let done = Instant::now();
+        eprintln!(
+            "lex: {:?}, parse: {:?}, interpret: {:?}, total: {:?}",
+            lexed - start,
+            parsed - lexed,
+            done - parsed,
+            done - start
+        );
+    }
+    ok
+}
+
+// Re-runs the script every time its modification time changes
+fn watch_file(file_name: &str, std_path: &Option<String>, time: bool) {
+    let modified = || fs::metadata(file_name).and_then(|m| m.modified()).ok();
+
+    let mut last_modified = modified();
+    run_file(file_name, std_path, time);
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let current = modified();
+        if current.is_some() && current != last_modified {
+            last_modified = current;
+            println!("\n---------- {file_name} changed ----------");
+            run_file(file_name, std_path, time);
+        }
     }
 }
 
 fn main() {
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("Can't start the interpreter thread");
+    if interpreter.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn run() {
     let args: Vec<String> = std::env::args().collect();
 
     if args.len() < 2 {
@@ -43,12 +115,15 @@ fn main() {
             .unwrap()
             .to_string();
         interpret_mode(&mut Interpreter::new(path, None));
+        return;
     }
 
     let args: Vec<String> = env::args().skip(1).collect();
 
     let mut std_path: Option<String> = None;
     let mut file_name: Option<String> = None;
+    let mut watch = false;
+    let mut time = false;
 
     let mut i = 0;
     while i < args.len() {
@@ -59,9 +134,11 @@ fn main() {
                     std_path = Some(args[i].clone());
                 } else {
                     eprintln!("Expected a path after --std");
-                    std::process::exit(1);
+                    process::exit(1);
                 }
             }
+            "--watch" => watch = true,
+            "--time" => time = true,
             arg if arg.ends_with(".iok") => {
                 file_name = Some(arg.to_string());
             }
@@ -70,44 +147,22 @@ fn main() {
         i += 1;
     }
 
-    if file_name.is_none() {
-        let path = env::current_dir()
-            .expect("Can't Access Dir")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let mut vm = Interpreter::new(path, std_path);
-        interpret_mode(&mut vm);
-    } else {
-        let file_name = file_name.unwrap().clone();
-
-        let mut input = String::new();
-        let mut file =
-            File::open(file_name.clone()).expect(&format!("Can't open file {}", file_name.clone()));
-        file.read_to_string(&mut input).expect("can't read file");
-
-        let mut lexer = Lexer::new(&input);
-        let tokens = lexer.tokenize();
-
-        let mut parser = Parser::new(tokens);
-
-        let parsed_tree = parser.parse_tokens();
-
-        let dir_path = Path::new(&file_name);
-        let path = if let Ok(abs_path) = dir_path.canonicalize() {
-            if let Some(parent) = abs_path.parent() {
-                parent.to_str().unwrap().to_string()
-            } else {
-                String::from("/")
+    match file_name {
+        None => {
+            let path = env::current_dir()
+                .expect("Can't Access Dir")
+                .to_str()
+                .unwrap()
+                .to_string();
+            let mut vm = Interpreter::new(path, std_path);
+            interpret_mode(&mut vm);
+        }
+        Some(file_name) => {
+            if watch {
+                watch_file(&file_name, &std_path, time);
+            } else if !run_file(&file_name, &std_path, time) {
+                process::exit(1);
             }
-        } else {
-            String::from("/")
-        };
-
-        let mut interpreter = Interpreter::new(path, std_path);
-
-        parsed_tree.iter().for_each(|stmt| {
-            interpreter.interpret(stmt);
-        });
+        }
     }
 }
@@ -1,21 +1,37 @@
+use crate::heap::Heap;
+use crate::lexer::Loc;
 use crate::parser::Tree;
 use crate::std_native::NativeFn;
-use core::ops::{AddAssign, BitAnd, Not, Shl, Shr};
+use core::ops::{AddAssign, BitAnd, BitXor, Not, Shl, Shr};
 use rustc_hash::FxHashMap;
-use std::{fmt, ops::BitOr};
+use std::{cell::RefCell, cmp::Ordering, fmt, ops::BitOr, rc::Rc};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default)]
 pub enum Object {
     String(Box<String>),
     Number(f64),
     Bool(bool),
     List(Vec<Object>),
-    Range(f64, f64),
+    Map(FxHashMap<String, Object>),
+    // Start, exclusive end and step. The step is a whole number and never 0,
+    // without one given it is -1 when end < start so `10..0` counts down
+    Range(f64, f64, f64),
     Ret(Box<Object>),
+    // `captured` holds the locals the body uses that were visible where the
+    // function was defined, with the id of the scope each came from. A call
+    // binds them above its arguments, so they win over any variable of the
+    // same name at the call site. While the defining scope is still running
+    // the call starts from its current value and stores the final one back,
+    // so nested fns share the locals of their parent. Once it has ended the
+    // copy taken at creation is used and changes don't outlive the call.
+    // Functions defined at the top level capture nothing
     Fn {
         name: String,
         args: Vec<(String, Object)>,
+        // `...name`, collects the args past the named ones into a list
+        rest: Option<String>,
         body: Vec<Tree>,
+        captured: Box<FxHashMap<String, (u64, Object)>>,
     },
     NativeFn {
         name: String,
@@ -34,18 +50,254 @@ pub enum Object {
         name: String,
         namespace: Box<FxHashMap<String, Object>>,
     },
+    // A runtime failure, it stops blocks, loops and calls like `Ret` does
+    // until it reaches the top level. `loc` is where it was raised when known
+    Error {
+        msg: Box<String>,
+        loc: Option<Loc>,
+    },
+    // A value made immutable by `freeze`. Only variables and containers hold
+    // it, reading one gives the plain value back, a copy that can be changed
+    Frozen(Box<Object>),
+    // A mutable cell made by `ref(value)`. Copying a ref copies the handle, so
+    // every copy sees what `set` stores. It is the only way to share state,
+    // everything else is copied on assignment and when passed to functions.
+    // A cell that ends up holding itself is never freed
+    Ref(Shared),
+    // A priority queue made by `heap()`, copied on assignment like a list
+    Heap(Box<Heap>),
+    // A whole number too big to be exact as an f64, only made with `bignum`
+    #[cfg(feature = "bignum")]
+    BigInt(Box<crate::bigint::BigInt>),
+    #[default]
     Null,
     Invalid,
 }
 
+// Handle of an `Object::Ref`. Two handles are equal if they point to the same
+// cell or the cells hold equal values. Printing and comparing stop at
+// `MAX_DEPTH` for a cell that contains itself, two different self-containing
+// cells compare as unequal
+#[derive(Clone, Debug)]
+pub struct Shared(pub Rc<RefCell<Object>>);
+
+impl Shared {
+    pub fn new(value: Object) -> Shared {
+        Shared(Rc::new(RefCell::new(value)))
+    }
+
+    pub fn get(&self) -> Object {
+        self.0.borrow().clone()
+    }
+
+    pub fn set(&self, value: Object) {
+        *self.0.borrow_mut() = value;
+    }
+
+    fn eq_depth(&self, other: &Shared, depth: usize) -> bool {
+        Rc::ptr_eq(&self.0, &other.0) || self.0.borrow().eq_depth(&other.0.borrow(), depth)
+    }
+}
+
+// Derived equality except for instances, which are equal when they belong to
+// structs of the same name and have equal fields. Their definitions aren't
+// compared, so method bodies don't matter
+impl PartialEq for Object {
+    fn eq(&self, other: &Object) -> bool {
+        self.eq_depth(other, 0)
+    }
+}
+
+// Name of a `StructDef`, what instances are told apart by
+fn struct_name(def: &Object) -> Option<&str> {
+    match def {
+        Object::StructDef { name, .. } => Some(name),
+        _ => None,
+    }
+}
+
+impl PartialEq for Shared {
+    fn eq(&self, other: &Shared) -> bool {
+        self.eq_depth(other, 0)
+    }
+}
+
+// How deep printing and deep comparison follow nested values
+pub const MAX_DEPTH: usize = 256;
+
+// Every place that needs an integer (indexing, ranges, bit ops, repetition)
+// converts through here. The fraction is truncated toward zero, NaN, the
+// infinities and anything outside the i64 range give None
+pub fn to_int(n: f64) -> Option<i64> {
+    // i64::MAX isn't representable as an f64, 2^63 is the first value past it
+    if n.is_finite() && n >= i64::MIN as f64 && n < 9_223_372_036_854_775_808.0 {
+        Some(n as i64)
+    } else {
+        None
+    }
+}
+
+// Step of a range written without one
+pub fn default_step(start: f64, end: f64) -> f64 {
+    if end < start {
+        -1.0
+    } else {
+        1.0
+    }
+}
+
+// The integers a range visits, counting down for a negative step. The bounds
+// are truncated like any integer, None if one is out of the integer range
+pub fn range_items(start: f64, end: f64, step: f64) -> Option<Box<dyn Iterator<Item = i64>>> {
+    let (start, end, step) = (to_int(start)?, to_int(end)?, to_int(step)?);
+    let by = step.unsigned_abs() as usize;
+    if step > 0 {
+        Some(Box::new((start..end).step_by(by)))
+    } else {
+        Some(Box::new((end.saturating_add(1)..=start).rev().step_by(by)))
+    }
+}
+
 impl Object {
+    pub fn error(msg: impl Into<String>) -> Object {
+        Object::Error {
+            msg: Box::new(msg.into()),
+            loc: None,
+        }
+    }
+
+    // `==` behind the PartialEq impl. Refs can make a value contain itself,
+    // nesting past `MAX_DEPTH` compares as unequal instead of overflowing
+    #[allow(unpredictable_function_pointer_comparisons)]
+    fn eq_depth(&self, other: &Object, depth: usize) -> bool {
+        if depth > MAX_DEPTH {
+            return false;
+        }
+        let depth = depth + 1;
+        match (self, other) {
+            (Object::String(l), Object::String(r)) => l == r,
+            (Object::Number(l), Object::Number(r)) => l == r,
+            (Object::Bool(l), Object::Bool(r)) => l == r,
+            (Object::List(l), Object::List(r)) => {
+                l.len() == r.len() && l.iter().zip(r).all(|(l, r)| l.eq_depth(r, depth))
+            }
+            (Object::Map(l), Object::Map(r)) => Self::maps_eq(l, r, depth),
+            (Object::Range(l_start, l_end, l_step), Object::Range(r_start, r_end, r_step)) => {
+                l_start == r_start && l_end == r_end && l_step == r_step
+            }
+            (Object::Ret(l), Object::Ret(r)) => l.eq_depth(r, depth),
+            (
+                Object::Fn {
+                    name: l_name,
+                    args: l_args,
+                    rest: l_rest,
+                    body: l_body,
+                    captured: l_captured,
+                },
+                Object::Fn {
+                    name: r_name,
+                    args: r_args,
+                    rest: r_rest,
+                    body: r_body,
+                    captured: r_captured,
+                },
+            ) => {
+                l_name == r_name
+                    && l_args == r_args
+                    && l_rest == r_rest
+                    && l_body == r_body
+                    && l_captured.len() == r_captured.len()
+                    && l_captured.iter().all(|(name, (l_id, l))| {
+                        r_captured
+                            .get(name)
+                            .is_some_and(|(r_id, r)| l_id == r_id && l.eq_depth(r, depth))
+                    })
+            }
+            (
+                Object::NativeFn {
+                    name: l_name,
+                    function: l_fn,
+                },
+                Object::NativeFn {
+                    name: r_name,
+                    function: r_fn,
+                },
+            ) => l_name == r_name && l_fn == r_fn,
+            (
+                Object::StructDef {
+                    name: l_name,
+                    fields: l_fields,
+                    methods: l_methods,
+                },
+                Object::StructDef {
+                    name: r_name,
+                    fields: r_fields,
+                    methods: r_methods,
+                },
+            ) => {
+                l_name == r_name
+                    && Self::maps_eq(l_fields, r_fields, depth)
+                    && l_methods == r_methods
+            }
+            (
+                Object::Instance {
+                    struct_def: l_def,
+                    fields: l,
+                },
+                Object::Instance {
+                    struct_def: r_def,
+                    fields: r,
+                },
+            ) => struct_name(l_def) == struct_name(r_def) && Self::maps_eq(l, r, depth),
+            (
+                Object::NameSpace {
+                    name: l_name,
+                    namespace: l,
+                },
+                Object::NameSpace {
+                    name: r_name,
+                    namespace: r,
+                },
+            ) => l_name == r_name && Self::maps_eq(l, r, depth),
+            (
+                Object::Error {
+                    msg: l_msg,
+                    loc: l_loc,
+                },
+                Object::Error {
+                    msg: r_msg,
+                    loc: r_loc,
+                },
+            ) => l_msg == r_msg && l_loc == r_loc,
+            // Freezing doesn't change the value, it equals the plain one
+            (Object::Frozen(l), r) => l.eq_depth(r, depth),
+            (l, Object::Frozen(r)) => l.eq_depth(r, depth),
+            (Object::Ref(l), Object::Ref(r)) => l.eq_depth(r, depth),
+            (Object::Heap(l), Object::Heap(r)) => l.eq_by(r, |l, r| l.eq_depth(r, depth)),
+            #[cfg(feature = "bignum")]
+            (l @ Object::BigInt(_), r) | (l, r @ Object::BigInt(_)) => {
+                crate::bigint::compare(l, r) == Some(Ordering::Equal)
+            }
+            (Object::Null, Object::Null) | (Object::Invalid, Object::Invalid) => true,
+            _ => false,
+        }
+    }
+
+    fn maps_eq(l: &FxHashMap<String, Object>, r: &FxHashMap<String, Object>, depth: usize) -> bool {
+        l.len() == r.len()
+            && l.iter()
+                .all(|(key, value)| r.get(key).is_some_and(|other| value.eq_depth(other, depth)))
+    }
+
     pub fn to_string_obj(&self) -> Object {
         match self {
             Object::String(ref s) => Object::String(Box::new(s.to_string())),
             Object::Number(num) => Object::String(Box::new(num.to_string())),
+            #[cfg(feature = "bignum")]
+            Object::BigInt(n) => Object::String(Box::new(n.to_string())),
             Object::Bool(b) => Object::String(Box::new(b.to_string())),
-            Object::Null => Object::String(Box::new(String::new())),
-            _ => Object::String(Box::new(String::new())),
+            Object::Null => Object::String(Box::default()),
+            _ => Object::String(Box::default()),
         }
     }
 
@@ -53,6 +305,8 @@ impl Object {
         match self {
             Object::String(s) => s.parse().map_or(Object::Invalid, Object::Number),
             Object::Number(n) => Object::Number(*n),
+            #[cfg(feature = "bignum")]
+            Object::BigInt(n) => Object::Number(n.to_f64()),
             Object::Bool(b) => Object::Number(if *b { 1.0 } else { 0.0 }),
             Object::Null => Object::Number(0.0),
             _ => Object::Number(0.0),
@@ -63,12 +317,134 @@ impl Object {
         match self {
             Object::String(s) => Object::Bool(!s.is_empty()),
             Object::Number(num) => Object::Bool(*num != 0.0),
+            #[cfg(feature = "bignum")]
+            Object::BigInt(_) => Object::Bool(true),
             Object::Bool(b) => Object::Bool(*b),
             Object::Null => Object::Bool(false),
             _ => Object::Bool(false),
         }
     }
 
+    // Structural equality where numbers may differ by up to `eps`. Lists, maps
+    // and instances compare their items, instances also need the same struct.
+    // A ref can hold itself, nesting past `MAX_DEPTH` compares as unequal
+    // rather than overflowing the stack
+    pub fn deep_equal(&self, other: &Object, eps: f64) -> bool {
+        self.deep_equal_depth(other, eps, 0)
+    }
+
+    fn deep_equal_depth(&self, other: &Object, eps: f64, depth: usize) -> bool {
+        if depth > MAX_DEPTH {
+            return false;
+        }
+        let depth = depth + 1;
+        match (self, other) {
+            (Object::Frozen(l), r) => l.deep_equal_depth(r, eps, depth),
+            (Object::Ref(l), Object::Ref(r)) => {
+                Rc::ptr_eq(&l.0, &r.0) || l.0.borrow().deep_equal_depth(&r.0.borrow(), eps, depth)
+            }
+            (l, Object::Frozen(r)) => l.deep_equal_depth(r, eps, depth),
+            (Object::Number(l), Object::Number(r)) => l == r || (l - r).abs() <= eps,
+            (Object::List(l), Object::List(r)) => {
+                l.len() == r.len()
+                    && l.iter()
+                        .zip(r)
+                        .all(|(l, r)| l.deep_equal_depth(r, eps, depth))
+            }
+            (Object::Map(l), Object::Map(r)) => Self::fields_equal(l, r, eps, depth),
+            (
+                Object::Instance {
+                    struct_def: l_def,
+                    fields: l,
+                },
+                Object::Instance {
+                    struct_def: r_def,
+                    fields: r,
+                },
+            ) => struct_name(l_def) == struct_name(r_def) && Self::fields_equal(l, r, eps, depth),
+            _ => self.eq_depth(other, depth),
+        }
+    }
+
+    fn fields_equal(
+        l: &FxHashMap<String, Object>,
+        r: &FxHashMap<String, Object>,
+        eps: f64,
+        depth: usize,
+    ) -> bool {
+        l.len() == r.len()
+            && l.iter().all(|(key, value)| {
+                r.get(key)
+                    .is_some_and(|other| value.deep_equal_depth(other, eps, depth))
+            })
+    }
+
+    // The value inside a `Frozen`, anything else as it is
+    pub fn unfrozen(self) -> Object {
+        match self {
+            Object::Frozen(inner) => *inner,
+            obj => obj,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Frozen(inner) => inner.type_name(),
+            Object::Ref(_) => "ref",
+            Object::Heap(_) => "heap",
+            #[cfg(feature = "bignum")]
+            Object::BigInt(_) => "number",
+            Object::String(_) => "string",
+            Object::Number(_) => "number",
+            Object::Bool(_) => "bool",
+            Object::List(_) => "list",
+            Object::Map(_) => "map",
+            Object::Range(..) => "range",
+            Object::Ret(obj) => obj.type_name(),
+            Object::Fn { .. } | Object::NativeFn { .. } => "fn",
+            Object::StructDef { .. } => "struct",
+            Object::Instance { .. } => "instance",
+            Object::NameSpace { .. } => "namespace",
+            Object::Error { .. } => "error",
+            Object::Null => "null",
+            Object::Invalid => "invalid",
+        }
+    }
+
+    // Number of objects held by this value, including itself
+    pub fn count_objects(&self) -> usize {
+        1 + match self {
+            Object::List(list) => list.iter().map(Object::count_objects).sum(),
+            Object::Map(map) => map.values().map(Object::count_objects).sum(),
+            Object::Heap(heap) => heap.values().map(Object::count_objects).sum(),
+            Object::Ret(obj) | Object::Frozen(obj) => obj.count_objects(),
+            Object::Fn { args, captured, .. } => {
+                args.iter()
+                    .map(|(_, obj)| obj.count_objects())
+                    .sum::<usize>()
+                    + captured
+                        .values()
+                        .map(|(_, obj)| obj.count_objects())
+                        .sum::<usize>()
+            }
+            Object::StructDef {
+                fields, methods, ..
+            } => fields
+                .values()
+                .chain(methods.values())
+                .map(Object::count_objects)
+                .sum(),
+            Object::Instance { struct_def, fields } => {
+                struct_def.count_objects()
+                    + fields.values().map(Object::count_objects).sum::<usize>()
+            }
+            Object::NameSpace { namespace, .. } => {
+                namespace.values().map(Object::count_objects).sum()
+            }
+            _ => 0,
+        }
+    }
+
     pub fn get_string_value(&self) -> String {
         if let Object::String(s) = self.to_string_obj() {
             *s
@@ -77,11 +453,11 @@ impl Object {
         }
     }
 
-    pub fn get_number_value(&self) -> f64 {
-        if let Object::Number(n) = self {
-            *n
-        } else {
-            0.0
+    // The integer value of a number, see `to_int`
+    pub fn get_int_value(&self) -> Option<i64> {
+        match self {
+            Object::Number(n) => to_int(*n),
+            _ => None,
         }
     }
 
@@ -93,7 +469,37 @@ impl Object {
         }
     }
 
-    pub fn get_list_index(&self, i: usize) -> Object {
+    // Numbers compare numerically and strings lexicographically, anything else is unordered
+    pub fn compare(&self, other: &Object) -> Option<Ordering> {
+        #[cfg(feature = "bignum")]
+        if let Some(ordering) = crate::bigint::compare(self, other) {
+            return Some(ordering);
+        }
+        match (self, other) {
+            (Object::Number(l), Object::Number(r)) => l.partial_cmp(r),
+            (Object::String(l), Object::String(r)) => Some(l.cmp(r)),
+            _ => None,
+        }
+    }
+
+    // Negative indices count from the end, `-1` is the last item. Returns None
+    // for a negative index past the start
+    pub fn resolve_index(&self, i: i64) -> Option<usize> {
+        if i >= 0 {
+            return Some(i as usize);
+        }
+        let len = match self {
+            Object::String(s) => s.chars().count(),
+            _ => self.get_len(),
+        };
+        len.checked_sub(i.unsigned_abs() as usize)
+    }
+
+    pub fn get_list_index(&self, i: i64) -> Object {
+        let i = match self.resolve_index(i) {
+            Some(i) => i,
+            None => return Object::Null,
+        };
         match self {
             Object::List(list) => list.get(i).cloned().unwrap_or(Object::Null),
             Object::String(s) => s
@@ -104,7 +510,46 @@ impl Object {
         }
     }
 
-    pub fn get_list_index_mut(&mut self, i: usize) -> Option<&mut Object> {
+    // Items from `start` up to but not including `end`. Both ends may be
+    // negative and are clamped to the bounds, a reversed range is empty
+    pub fn slice(&self, start: i64, end: i64) -> Object {
+        let len = match self {
+            Object::String(s) => s.chars().count(),
+            _ => self.get_len(),
+        };
+        let start = self.resolve_index(start).unwrap_or(0).min(len);
+        let end = self.resolve_index(end).unwrap_or(0).min(len).max(start);
+        match self {
+            Object::List(list) => Object::List(list[start..end].to_vec()),
+            Object::String(s) => {
+                Object::String(Box::new(s.chars().skip(start).take(end - start).collect()))
+            }
+            _ => Object::Null,
+        }
+    }
+
+    pub fn get_map_key(&self, key: &str) -> Object {
+        match self {
+            Object::Map(map) => map.get(key).cloned().unwrap_or(Object::Null),
+            _ => Object::Null,
+        }
+    }
+
+    pub fn get_map_key_mut(&mut self, key: &str) -> Option<&mut Object> {
+        match self {
+            Object::Map(ref mut map) => map.get_mut(key),
+            _ => None,
+        }
+    }
+
+    pub fn set_map_key(&mut self, key: String, value: Object) {
+        if let Object::Map(map) = self {
+            map.insert(key, value);
+        }
+    }
+
+    pub fn get_list_index_mut(&mut self, i: i64) -> Option<&mut Object> {
+        let i = self.resolve_index(i)?;
         match self {
             Object::List(ref mut list) => list.get_mut(i),
             Object::String(_) => Some(self),
@@ -123,8 +568,35 @@ impl Object {
         }
     }
 
+    // Instance of a struct definition with the fields taken from `values`.
+    // Missing fields keep their defaults and unknown keys are an error
+    pub fn instance_from(&self, values: &FxHashMap<String, Object>) -> Object {
+        let Object::StructDef { name, fields, .. } = self else {
+            return Object::error(format!("Expected a struct found {}", self.type_name()));
+        };
+        let mut unknown: Vec<&String> =
+            values.keys().filter(|k| !fields.contains_key(*k)).collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            let unknown: Vec<&str> = unknown.iter().map(|k| k.as_str()).collect();
+            return Object::error(format!("{name} has no field {}", unknown.join(", ")));
+        }
+
+        let mut instance_fields = *fields.clone();
+        for (key, value) in values {
+            instance_fields.insert(key.clone(), value.clone());
+        }
+        Object::Instance {
+            struct_def: Box::new(self.clone()),
+            fields: instance_fields,
+        }
+    }
+
+    // `m.key` reads a map entry like `m["key"]`, which lets caught errors be
+    // read as `e.msg`
     pub fn get_field(&self, name: &String) -> Option<&Object> {
         match self {
+            Object::Map(map) => map.get(name),
             Object::Instance {
                 struct_def: _,
                 ref fields,
@@ -139,30 +611,41 @@ impl Object {
         }
     }
 
-    pub fn set_list_index(&mut self, i: usize, value: Object) {
+    // Lists can only replace existing items, strings are padded with spaces
+    // up to the index
+    pub fn set_list_index(&mut self, index: i64, value: Object) -> Result<(), Object> {
+        let out_of_range =
+            |len: usize| Object::error(format!("Index {index} is out of range for length {len}"));
+        let i = match self.resolve_index(index) {
+            Some(i) => i,
+            None => return Err(out_of_range(self.get_len())),
+        };
         match self {
-            Object::List(list) => {
-                list[i] = value;
-            }
+            Object::List(list) => match list.get_mut(i) {
+                Some(item) => *item = value,
+                None => return Err(out_of_range(list.len())),
+            },
             Object::String(s) => {
-                if i >= s.len() {
-                    let needed = i + 1 - s.len();
-                    s.reserve(needed);
-                    s.push_str(&" ".repeat(needed)); // extend exactly to index i
+                let len = s.chars().count();
+                if i >= len {
+                    s.push_str(&" ".repeat(i + 1 - len)); // extend exactly to index i
                 }
-                // Replace one character at position i:
-                if let Object::String(v) = value {
-                    s.replace_range(i..i + 1, &v);
+                // Replace the i-th character, indices count chars not bytes
+                if let (Object::String(v), Some((start, c))) = (value, s.char_indices().nth(i)) {
+                    s.replace_range(start..start + c.len_utf8(), &v);
                 }
             }
             _ => {}
         }
+        Ok(())
     }
 
     pub fn get_len(&self) -> usize {
         match self {
             Object::String(str) => str.len(),
             Object::List(list) => list.len(),
+            Object::Map(map) => map.len(),
+            Object::Heap(heap) => heap.len(),
             _ => 0,
         }
     }
@@ -179,10 +662,22 @@ impl Object {
 
     pub fn pop(&mut self) -> Object {
         match self {
-            Object::List(ref mut list) => list.pop().expect("List is empty"),
-            Object::String(ref mut s) => {
-                Object::String(Box::new(s.pop().expect("String is Empty").to_string()))
-            }
+            Object::List(ref mut list) => list
+                .pop()
+                .unwrap_or_else(|| Object::error("Can't pop from an empty list")),
+            Object::String(ref mut s) => s.pop().map_or_else(
+                || Object::error("Can't pop from an empty string"),
+                |c| Object::String(Box::new(c.to_string())),
+            ),
+            _ => Object::Invalid,
+        }
+    }
+
+    // Removes and returns the first element, null when the list is empty
+    pub fn dequeue(&mut self) -> Object {
+        match self {
+            Object::List(ref mut list) if !list.is_empty() => list.remove(0),
+            Object::List(_) => Object::error("Can't dequeue from an empty list"),
             _ => Object::Invalid,
         }
     }
@@ -190,32 +685,102 @@ impl Object {
 
 impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_depth(f, 0)
+    }
+}
+
+impl Object {
+    // Nested values past `MAX_DEPTH` print as `...`
+    fn fmt_depth(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        if depth > MAX_DEPTH {
+            return write!(f, "...");
+        }
         match self {
             Object::String(s) => write!(f, "{s}"),
             Object::Number(n) => write!(f, "{n}"),
+            #[cfg(feature = "bignum")]
+            Object::BigInt(n) => write!(f, "{n}"),
             Object::Bool(b) => write!(f, "{b}"),
             Object::List(list) => {
-                let list_str: Vec<String> = list.iter().map(|obj| obj.to_string()).collect();
-                write!(f, "[{}]", list_str.join(", "))
+                write!(f, "[")?;
+                for (i, obj) in list.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    obj.fmt_depth(f, depth + 1)?;
+                }
+                write!(f, "]")
             }
-            Object::Range(s, e) => write!(f, "{s}..{e}"),
-            Object::Ret(o) => write!(f, "Ret({o})"),
-            Object::Fn {
-                name,
-                args,
-                body: _,
-            } => write!(f, "fn {name} ({:?})", args),
+            Object::Map(map) => {
+                // FxHashMap has no stable order, sort so output is deterministic
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                write!(f, "{{")?;
+                for (i, key) in keys.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{key}: ")?;
+                    map[key].fmt_depth(f, depth + 1)?;
+                }
+                write!(f, "}}")
+            }
+            Object::Range(s, e, step) if *step == default_step(*s, *e) => write!(f, "{s}..{e}"),
+            Object::Range(s, e, step) => write!(f, "{s}..{e} step {step}"),
+            Object::Heap(heap) => {
+                write!(f, "heap[")?;
+                for (i, obj) in heap.values().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    obj.fmt_depth(f, depth + 1)?;
+                }
+                write!(f, "]")
+            }
+            Object::Frozen(o) => o.fmt_depth(f, depth),
+            Object::Ref(cell) => {
+                write!(f, "ref(")?;
+                cell.0.borrow().fmt_depth(f, depth + 1)?;
+                write!(f, ")")
+            }
+            Object::Ret(o) => {
+                write!(f, "Ret(")?;
+                o.fmt_depth(f, depth + 1)?;
+                write!(f, ")")
+            }
+            Object::Fn { name, args, .. } => write!(f, "fn {name} ({:?})", args),
             Object::NativeFn { name, .. } => write!(f, "NativeFn<{name}>"),
             Object::StructDef {
                 name,
                 fields: _,
                 methods: _,
             } => write!(f, "<{name}>"),
-            Object::Instance {
-                struct_def: def,
-                fields: _,
-            } => write!(f, "Object{def}"),
+            // `Point { x: 1, y: 2 }`, fields sorted like map keys
+            Object::Instance { struct_def, fields } => {
+                if let Object::StructDef { name, .. } = &**struct_def {
+                    write!(f, "{name} ")?;
+                }
+                let mut keys: Vec<&String> = fields.keys().collect();
+                keys.sort();
+                write!(f, "{{")?;
+                for (i, key) in keys.into_iter().enumerate() {
+                    write!(f, "{}{key}: ", if i > 0 { ", " } else { " " })?;
+                    fields[key].fmt_depth(f, depth + 1)?;
+                }
+                if fields.is_empty() {
+                    write!(f, "}}")
+                } else {
+                    write!(f, " }}")
+                }
+            }
             Object::NameSpace { name, .. } => write!(f, "@{name}"),
+            Object::Error {
+                msg,
+                loc: Some(loc),
+            } => {
+                write!(f, "error: {msg} at line {}:{}", loc.y, loc.x)
+            }
+            Object::Error { msg, loc: None } => write!(f, "error: {msg}"),
             Object::Null => write!(f, "null"),
             Object::Invalid => write!(f, "invalid"),
         }
@@ -257,11 +822,14 @@ impl AddAssign for Object {
     }
 }
 
+// Bitwise operators work on the numbers converted with `to_int`. Numbers
+// that don't fit and shifting by a negative amount or by 64 and more bits
+// give `Object::Invalid`
 impl BitAnd for Object {
     type Output = Object;
     fn bitand(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Number(l), Object::Number(r)) => Object::Number((l as i64 & r as i64) as f64),
+        match (self.get_int_value(), rhs.get_int_value()) {
+            (Some(l), Some(r)) => Object::Number((l & r) as f64),
             _ => Object::Invalid,
         }
     }
@@ -270,8 +838,18 @@ impl BitAnd for Object {
 impl BitOr for Object {
     type Output = Object;
     fn bitor(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Number(l), Object::Number(r)) => Object::Number((l as i64 | r as i64) as f64),
+        match (self.get_int_value(), rhs.get_int_value()) {
+            (Some(l), Some(r)) => Object::Number((l | r) as f64),
+            _ => Object::Invalid,
+        }
+    }
+}
+
+impl BitXor for Object {
+    type Output = Object;
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        match (self.get_int_value(), rhs.get_int_value()) {
+            (Some(l), Some(r)) => Object::Number((l ^ r) as f64),
             _ => Object::Invalid,
         }
     }
@@ -280,10 +858,11 @@ impl BitOr for Object {
 impl Shl for Object {
     type Output = Object;
     fn shl(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Number(l), Object::Number(r)) => {
-                Object::Number(((l as i32) << (r as i32)) as f64)
-            }
+        match (self.get_int_value(), rhs.get_int_value()) {
+            (Some(l), Some(r)) => u32::try_from(r)
+                .ok()
+                .and_then(|r| l.checked_shl(r))
+                .map_or(Object::Invalid, |n| Object::Number(n as f64)),
             _ => Object::Invalid,
         }
     }
@@ -291,17 +870,106 @@ impl Shl for Object {
 impl Shr for Object {
     type Output = Object;
     fn shr(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Number(l), Object::Number(r)) => {
-                Object::Number(((l as i32) >> (r as i32)) as f64)
-            }
+        match (self.get_int_value(), rhs.get_int_value()) {
+            (Some(l), Some(r)) => u32::try_from(r)
+                .ok()
+                .and_then(|r| l.checked_shr(r))
+                .map_or(Object::Invalid, |n| Object::Number(n as f64)),
             _ => Object::Invalid,
         }
     }
 }
 
-impl Default for Object {
-    fn default() -> Self {
-        Object::Null
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interpreter;
+
+    fn eval(src: &str) -> Object {
+        Interpreter::new(".".to_string(), None).eval_str(src)
+    }
+
+    #[test]
+    fn division_displays_whole_results_without_a_fraction() {
+        assert_eq!(eval("10 / 2").to_string(), "5");
+        assert_eq!(eval("7 / 2").to_string(), "3.5");
+        assert_eq!(eval("-7 / 2").to_string(), "-3.5");
+        assert_eq!(eval("math.floor_div(7, 2)").to_string(), "3");
+        assert_eq!(eval("math.floor_div(-7, 2)").to_string(), "-4");
+    }
+
+    #[test]
+    fn instances_compare_by_struct_name_and_fields() {
+        let prelude = "struct P { let x = 0\nfn get() => self.x }\nstruct Q { let x = 0 }\n";
+        let cases = [
+            ("P { x: 1 } == P { x: 1 }", true),
+            ("P { x: 1 } == P { x: 2 }", false),
+            ("P { x: 1 } == Q { x: 1 }", false),
+            (
+                "let a = P { x: 3 }\nlet b = P { x: 1 }\nb.x = 3\na == b",
+                true,
+            ),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(
+                eval(&format!("{prelude}{src}")),
+                Object::Bool(expected),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn frozen_values_equal_their_plain_value() {
+        assert_eq!(eval("freeze([1]) == [1]"), Object::Bool(true));
+        assert_eq!(
+            eval("{\"a\": freeze([1])} == {\"a\": [1]}"),
+            Object::Bool(true)
+        );
+        assert_eq!(eval("[freeze([1])] == [[2]]"), Object::Bool(false));
+    }
+
+    #[test]
+    fn equality_stops_at_the_depth_limit() {
+        let nested = |depth: usize| {
+            (0..depth).fold(Object::Number(1.0), |inner, _| Object::List(vec![inner]))
+        };
+        assert_eq!(nested(10), nested(10));
+        assert_ne!(nested(10), nested(11));
+        // Too deep to tell, so unequal instead of overflowing the stack
+        assert_ne!(nested(MAX_DEPTH + 10), nested(MAX_DEPTH + 10));
+
+        let a = Shared::new(Object::Null);
+        a.set(Object::List(vec![Object::Ref(a.clone())]));
+        let b = Shared::new(Object::Null);
+        b.set(Object::List(vec![Object::Ref(b.clone())]));
+        assert_eq!(a, a.clone());
+        assert_ne!(a, b);
+        assert!(!Object::Ref(a.clone()).deep_equal(&Object::Ref(b.clone()), 0.0));
+        // Break the cycles so the cells are freed
+        a.set(Object::Null);
+        b.set(Object::Null);
+    }
+
+    #[test]
+    fn self_containing_refs_compare_without_overflowing() {
+        let prelude = "let a = ref(0)\nset(a, [a])\nlet b = ref(0)\nset(b, [b])\n";
+        let cases = [
+            ("a == b", Object::Bool(false)),
+            ("a == a", Object::Bool(true)),
+            ("let xs = [a]\nxs.contains(b)", Object::Bool(false)),
+            ("let xs = [b, a]\nxs.index_of(a)", Object::Number(1.0)),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(eval(&format!("{prelude}{src}")), expected, "{src}");
+        }
+    }
+
+    #[test]
+    fn string_index_assignment_counts_chars() {
+        let text = |s: &str| Object::String(Box::new(s.to_string()));
+        assert_eq!(eval("let t = \"héllo\"\nt[-1] = \"X\"\nt"), text("héllX"));
+        assert_eq!(eval("let t = \"héllo\"\nt[1] = \"e\"\nt"), text("hello"));
+        assert_eq!(eval("let t = \"äö\"\nt[3] = \"ü\"\nt"), text("äö ü"));
     }
 }
@@ -1,31 +1,45 @@
-use crate::lexer::{Loc, Token, TokenType};
+use crate::lexer::{Loc, StringPart, Token, TokenType};
 use crate::logger::{ErrorType, Logger};
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::iter::Peekable;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Tree {
     Number(f64),
+    #[cfg(feature = "bignum")]
+    BigInt(crate::bigint::BigInt),
     Bool(bool),
     String(Box<String>),
+    // `"a {x} b"`, the parts are joined as strings
+    InterpString(Vec<Tree>),
     List(Vec<Tree>),
-    Ident(String),
+    Map(FxHashMap<String, Tree>),
+    // `Ident`, `FnCall`, `MemberAccess` and `BinOp` keep where they start so
+    // runtime errors raised by them can point at the source
+    Ident(String, Loc),
     Empty(),
     ListCall(Box<Tree>, Box<Tree>),
     FnCall {
         name: String,
         args: Vec<Tree>,
+        loc: Loc,
     },
     MemberAccess {
         target: Box<Tree>, // variable
         member: Box<Tree>, // field or method()
+        loc: Loc,
     },
 
     Ret(Box<Tree>),
-    BinOp(Box<Tree>, TokenType, Box<Tree>),
+    BinOp(Box<Tree>, TokenType, Box<Tree>, Loc),
     CmpOp(Box<Tree>, TokenType, Box<Tree>),
-    Range(Box<Tree>, Box<Tree>),
+    // `start..end` with an optional `step n`
+    Range(Box<Tree>, Box<Tree>, Option<Box<Tree>>),
     Let(String, Box<Tree>),
+    // `let [a, b] = list`
+    Destructure(Vec<String>, Box<Tree>),
+    // `...name`, the last parameter of a fn collecting the surplus args
+    Rest(String, Loc),
     Assign(Box<Tree>, Box<Tree>),
     If {
         expr: Box<Tree>,
@@ -51,6 +65,11 @@ pub enum Tree {
         args: Vec<Tree>,
         body: Vec<Tree>,
     },
+    Try {
+        body: Vec<Tree>,
+        err_name: String,
+        catch: Vec<Tree>,
+    },
     StructDef {
         name: Box<String>,
         fields: Vec<Tree>,
@@ -66,9 +85,117 @@ pub enum Tree {
     },
 }
 
+// i could use a crate for that  ig if i wanna use unicodes
+fn unescape(string: &str) -> String {
+    string
+        .replace("\\n", "\n")
+        .replace("\\t", "\t")
+        .replace("\\r", "\r")
+        .replace("\\\"", "\"")
+        .replace("\\{", "{")
+        .replace("\\}", "}")
+        .replace("\\$", "$")
+}
+
+impl Tree {
+    pub fn loc(&self) -> Option<Loc> {
+        match self {
+            Tree::Ident(_, loc)
+            | Tree::Rest(_, loc)
+            | Tree::BinOp(.., loc)
+            | Tree::FnCall { loc, .. }
+            | Tree::MemberAccess { loc, .. } => Some(*loc),
+            _ => None,
+        }
+    }
+
+    // Adds every variable name read or assigned in the tree to `names`,
+    // including inside nested fns. Field and method names are skipped
+    pub fn collect_names(&self, names: &mut FxHashSet<String>) {
+        let all = |trees: &[Tree], names: &mut FxHashSet<String>| {
+            for tree in trees {
+                tree.collect_names(names);
+            }
+        };
+        match self {
+            Tree::Ident(name, _) => {
+                names.insert(name.clone());
+            }
+            Tree::FnCall { name, args, .. } => {
+                names.insert(name.clone());
+                all(args, names);
+            }
+            Tree::MemberAccess { target, member, .. } => {
+                target.collect_names(names);
+                match &**member {
+                    Tree::FnCall { args, .. } => all(args, names),
+                    Tree::Ident(..) => {}
+                    other => other.collect_names(names),
+                }
+            }
+            Tree::StructInit { name, fields } => {
+                names.insert(name.to_string());
+                fields.values().for_each(|tree| tree.collect_names(names));
+            }
+            Tree::InterpString(trees) | Tree::List(trees) => all(trees, names),
+            Tree::Map(entries) => entries.values().for_each(|tree| tree.collect_names(names)),
+            Tree::ListCall(a, b)
+            | Tree::BinOp(a, _, b, _)
+            | Tree::CmpOp(a, _, b)
+            | Tree::Assign(a, b) => {
+                a.collect_names(names);
+                b.collect_names(names);
+            }
+            Tree::Range(start, end, step) => {
+                start.collect_names(names);
+                end.collect_names(names);
+                if let Some(step) = step {
+                    step.collect_names(names);
+                }
+            }
+            Tree::Ret(expr) | Tree::Let(_, expr) | Tree::Destructure(_, expr) => {
+                expr.collect_names(names)
+            }
+            Tree::If {
+                expr,
+                body,
+                els,
+                els_ifs,
+            } => {
+                expr.collect_names(names);
+                all(body, names);
+                all(els, names);
+                all(els_ifs, names);
+            }
+            Tree::ElsIf { expr, body }
+            | Tree::While { expr, body }
+            | Tree::For { expr, body, .. } => {
+                expr.collect_names(names);
+                all(body, names);
+            }
+            Tree::Fn { args, body, .. } => {
+                all(args, names);
+                all(body, names);
+            }
+            Tree::Try { body, catch, .. } => {
+                all(body, names);
+                all(catch, names);
+            }
+            Tree::StructDef {
+                fields, methods, ..
+            } => {
+                all(fields, names);
+                all(methods, names);
+            }
+            _ => {}
+        }
+    }
+}
+
 pub struct Parser {
     pub tokens: Vec<Token>,
     prev_token: Token,
+    logger: Logger,
 }
 
 impl Parser {
@@ -79,8 +206,23 @@ impl Parser {
                 token: TokenType::Null,
                 loc: Loc { x: 0, y: 0 },
             },
+            logger: Logger::default(),
+        }
+    }
+
+    // Errors also show the line of `source` they point at
+    pub fn with_source(tokens: Vec<Token>, source: &str) -> Self {
+        Parser {
+            logger: Logger::with_source(source),
+            ..Parser::new(tokens)
         }
     }
+
+    // Parsing errors reported so far, the trees are still returned after one
+    pub fn error_count(&self) -> usize {
+        self.logger.error_count()
+    }
+
     pub fn parse_tokens(&mut self) -> Vec<Tree> {
         let tokens_clone = self.tokens.clone();
         let mut iter: Peekable<std::slice::Iter<'_, Token>> = tokens_clone.iter().peekable();
@@ -93,111 +235,234 @@ impl Parser {
         trees
     }
 
+    // Operator levels from the loosest to the tightest binding:
+    // assignment, `|>`, `||`, `&&`, `..`, comparison, `|`, `^`, `&`, `<<` `>>`,
+    // `+` `-`, `*` `/`, then postfix `[i]` `.` `::` `++` `--`.
+    // Binary operators are left associative except `=` and `+=`
     fn parse_expression(
         &mut self,
         iter: &mut std::iter::Peekable<std::slice::Iter<Token>>,
     ) -> Tree {
-        let mut left = self.parse_term(iter);
+        let left = self.parse_pipe(iter);
+
+        let Some(op) = iter.peek().cloned() else {
+            return left;
+        };
+        match op.token {
+            TokenType::Equal => {
+                iter.next();
+                self.prev_token = op.clone();
+                let expr = self.parse_expression(iter);
+                Tree::Assign(Box::new(left), Box::new(expr))
+            }
+            TokenType::PlusEqu => {
+                iter.next();
+                self.prev_token = op.clone();
+                let right = self.parse_expression(iter);
+                Tree::Assign(
+                    Box::new(left.clone()),
+                    Box::new(Tree::BinOp(
+                        Box::new(left),
+                        TokenType::Plus,
+                        Box::new(right),
+                        op.loc,
+                    )),
+                )
+            }
+            _ => left,
+        }
+    }
+
+    // `value |> f(args)` is `f(value, args)`, chains read left to right. The
+    // whole left side is piped, `a + b |> f` is `f(a + b)`
+    fn parse_pipe(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        let mut left = self.parse_or(iter);
 
         while let Some(op) = iter.peek().cloned() {
-            match op.token {
-                TokenType::Plus | TokenType::Minus => {
-                    iter.next();
-                    let right = self.parse_term(iter);
-                    left = Tree::BinOp(Box::new(left), op.token.clone(), Box::new(right));
-                }
-                TokenType::DDot => {
-                    iter.next();
-                    let right = self.parse_expression(iter);
-                    left = Tree::Range(Box::new(left), Box::new(right));
-                }
-                TokenType::DPlus => {
-                    iter.next();
-                    left = Tree::Assign(
-                        Box::new(left.clone()),
-                        Box::new(Tree::BinOp(
-                            Box::new(left),
-                            TokenType::Plus,
-                            Box::new(Tree::Number(1.0)),
-                        )),
-                    );
-                }
-                TokenType::DMinus => {
-                    iter.next();
-                    left = Tree::Assign(
-                        Box::new(left.clone()),
-                        Box::new(Tree::BinOp(
-                            Box::new(left),
-                            TokenType::Minus,
-                            Box::new(Tree::Number(1.0)),
-                        )),
+            if op.token != TokenType::Pipe {
+                break;
+            }
+            iter.next();
+            self.prev_token = op.clone();
+            let right = self.parse_postfix(iter);
+            left = match Self::pipe_into(left, right) {
+                Some(call) => call,
+                None => {
+                    self.logger.error(
+                        "Expected a function call after |>",
+                        op.loc,
+                        ErrorType::Parsing,
                     );
+                    Tree::Empty()
                 }
-                TokenType::BitAnd | TokenType::BitOR => {
-                    iter.next();
-                    let right = self.parse_expression(iter);
-                    left = Tree::BinOp(Box::new(left), op.token.clone(), Box::new(right));
-                }
-                TokenType::Shl | TokenType::Shr => {
-                    iter.next();
-                    let right = self.parse_expression(iter);
-                    left = Tree::BinOp(Box::new(left), op.token.clone(), Box::new(right));
-                }
+            };
+        }
 
-                _ => break,
+        left
+    }
+
+    fn parse_or(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        self.parse_binary(iter, &[TokenType::Or], Self::parse_and)
+    }
+
+    fn parse_and(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        self.parse_binary(iter, &[TokenType::And], Self::parse_range)
+    }
+
+    // `a..b` or `a..b step n`, ranges don't chain. `step` is only special
+    // right after a range and before a number, a name, `(` or `-`, so a
+    // variable called step on the next line still works
+    fn parse_range(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        let left = self.parse_comparison(iter);
+        match iter.peek() {
+            Some(op) if op.token == TokenType::DDot => {
+                self.prev_token = iter.next().unwrap().clone();
+                let right = self.parse_comparison(iter);
+                let step_value = iter.clone().nth(1).map(|t| &t.token);
+                let step = match iter.peek() {
+                    Some(Token {
+                        token: TokenType::Ident(word),
+                        ..
+                    }) if word == "step"
+                        && matches!(
+                            step_value,
+                            Some(
+                                TokenType::Number(_)
+                                    | TokenType::Ident(_)
+                                    | TokenType::OpenParen
+                                    | TokenType::Minus
+                            )
+                        ) =>
+                    {
+                        self.prev_token = iter.next().unwrap().clone();
+                        Some(Box::new(self.parse_comparison(iter)))
+                    }
+                    _ => None,
+                };
+                Tree::Range(Box::new(left), Box::new(right), step)
             }
+            _ => left,
+        }
+    }
+
+    fn parse_comparison(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        self.parse_binary(
+            iter,
+            &[
+                TokenType::EquEqu,
+                TokenType::NotEqu,
+                TokenType::Greater,
+                TokenType::GreatEqu,
+                TokenType::Less,
+                TokenType::LessEqu,
+            ],
+            Self::parse_bit_or,
+        )
+    }
+
+    fn parse_bit_or(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        self.parse_binary(iter, &[TokenType::BitOR], Self::parse_bit_xor)
+    }
+
+    fn parse_bit_xor(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        self.parse_binary(iter, &[TokenType::BitXor], Self::parse_bit_and)
+    }
+
+    fn parse_bit_and(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        self.parse_binary(iter, &[TokenType::BitAnd], Self::parse_shift)
+    }
+
+    fn parse_shift(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        self.parse_binary(
+            iter,
+            &[TokenType::Shl, TokenType::Shr],
+            Self::parse_additive,
+        )
+    }
+
+    fn parse_additive(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        self.parse_binary(iter, &[TokenType::Plus, TokenType::Minus], Self::parse_term)
+    }
+
+    fn parse_term(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        self.parse_binary(
+            iter,
+            &[TokenType::Multiply, TokenType::Divide],
+            Self::parse_postfix,
+        )
+    }
+
+    // One left associative level, `operand (op operand)*` for any of `ops`
+    fn parse_binary(
+        &mut self,
+        iter: &mut Peekable<std::slice::Iter<Token>>,
+        ops: &[TokenType],
+        operand: fn(&mut Self, &mut Peekable<std::slice::Iter<Token>>) -> Tree,
+    ) -> Tree {
+        let mut left = operand(self, iter);
+
+        while let Some(op) = iter.peek().cloned() {
+            if !ops.contains(&op.token) {
+                break;
+            }
+            iter.next();
             self.prev_token = op.clone();
+            let right = operand(self, iter);
+            left = match op.token {
+                TokenType::EquEqu
+                | TokenType::NotEqu
+                | TokenType::Greater
+                | TokenType::GreatEqu
+                | TokenType::Less
+                | TokenType::LessEqu
+                | TokenType::And
+                | TokenType::Or => Tree::CmpOp(Box::new(left), op.token.clone(), Box::new(right)),
+                _ => Tree::BinOp(Box::new(left), op.token.clone(), Box::new(right), op.loc),
+            };
         }
 
         left
     }
 
-    fn parse_term(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+    // Inserts `value` as the first argument of the call on the right of `|>`
+    fn pipe_into(value: Tree, call: Tree) -> Option<Tree> {
+        match call {
+            Tree::Ident(name, loc) => Some(Tree::FnCall {
+                name,
+                args: vec![value],
+                loc,
+            }),
+            Tree::FnCall {
+                name,
+                mut args,
+                loc,
+            } => {
+                args.insert(0, value);
+                Some(Tree::FnCall { name, args, loc })
+            }
+            // Functions in a namespace, `x |> math::sqrt`
+            Tree::MemberAccess {
+                target,
+                member,
+                loc,
+            } => Some(Tree::MemberAccess {
+                target,
+                member: Box::new(Self::pipe_into(value, *member)?),
+                loc,
+            }),
+            _ => None,
+        }
+    }
+
+    // Indexing, member access and `++`/`--` after a factor
+    fn parse_postfix(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
         let mut left = self.parse_factor(iter);
 
         while let Some(op) = iter.peek().cloned() {
             match op.token {
-                TokenType::Multiply | TokenType::Divide => {
-                    iter.next();
-                    let right = self.parse_factor(iter);
-                    left = Tree::BinOp(Box::new(left), op.token.clone(), Box::new(right));
-                }
-                TokenType::Equal => {
-                    iter.next();
-                    let expr = self.parse_expression(iter);
-                    left = Tree::Assign(Box::new(left), Box::new(expr));
-                }
-                TokenType::EquEqu | TokenType::NotEqu => {
-                    iter.next();
-                    let right = self.parse_factor(iter);
-                    left = Tree::CmpOp(Box::new(left), op.token.clone(), Box::new(right));
-                }
-
-                TokenType::PlusEqu => {
-                    iter.next();
-                    let right = self.parse_expression(iter);
-                    left = Tree::Assign(
-                        Box::new(left.clone()),
-                        Box::new(Tree::BinOp(
-                            Box::new(left),
-                            TokenType::Plus,
-                            Box::new(right),
-                        )),
-                    );
-                }
-                TokenType::Greater | TokenType::GreatEqu | TokenType::Less | TokenType::LessEqu => {
-                    iter.next();
-                    let right = self.parse_term(iter);
-                    left = Tree::CmpOp(Box::new(left), op.token.clone(), Box::new(right));
-                }
-                TokenType::And | TokenType::Or => {
-                    iter.next();
-                    let right = self.parse_expression(iter);
-                    left = Tree::CmpOp(Box::new(left), op.token.clone(), Box::new(right));
-                }
                 TokenType::OpenSquare => {
                     iter.next();
-                    while let Some(peek) = iter.peek().clone() {
+                    while let Some(peek) = iter.peek() {
                         match peek.token {
                             TokenType::CloseSquare => {
                                 iter.next();
@@ -216,8 +481,26 @@ impl Parser {
                     left = Tree::MemberAccess {
                         target: Box::new(left),
                         member,
+                        loc: op.loc,
                     };
                 }
+                TokenType::DPlus | TokenType::DMinus => {
+                    iter.next();
+                    let op_token = if op.token == TokenType::DPlus {
+                        TokenType::Plus
+                    } else {
+                        TokenType::Minus
+                    };
+                    left = Tree::Assign(
+                        Box::new(left.clone()),
+                        Box::new(Tree::BinOp(
+                            Box::new(left),
+                            op_token,
+                            Box::new(Tree::Number(1.0)),
+                            op.loc,
+                        )),
+                    );
+                }
 
                 _ => break,
             }
@@ -244,12 +527,30 @@ impl Parser {
                         }
                     }
                 }
-                _ => Logger::error("Expected {{", peek.loc, ErrorType::Parsing),
+                _ => self
+                    .logger
+                    .error("Expected {{", peek.loc, ErrorType::Parsing),
             }
         }
         body
     }
 
+    // Next token without consuming it, reports an error at the end of input
+    fn peek_or_eof<'t>(
+        &self,
+        iter: &mut Peekable<std::slice::Iter<'t, Token>>,
+    ) -> Option<&'t Token> {
+        let token = iter.peek().copied();
+        if token.is_none() {
+            self.logger.error(
+                "Unexpected end of input",
+                self.prev_token.loc,
+                ErrorType::Parsing,
+            );
+        }
+        token
+    }
+
     // TODO Use this in all functions
     // Helper function to check and consume the expected token
     fn expect_token(
@@ -263,7 +564,7 @@ impl Parser {
                 iter.next();
                 return Some(token.token.clone());
             } else {
-                Logger::error(
+                self.logger.error(
                     &format!(
                         "Expected token: {:?}, but found: {:?}",
                         expected, token.token
@@ -273,7 +574,7 @@ impl Parser {
                 );
             }
         } else {
-            Logger::error(
+            self.logger.error(
                 &format!("Expected token: {:?}, but reached end of input", expected),
                 self.prev_token.loc,
                 ErrorType::Parsing,
@@ -282,34 +583,43 @@ impl Parser {
         None
     }
 
+    // Parses the `elsif`/`els` chain after an `if` body. Branches are kept in
+    // source order and `els` has to be the last one
     fn next_case(
         &mut self,
         iter: &mut Peekable<std::slice::Iter<'_, Token>>,
         els: &mut Vec<Tree>,
         els_ifs: &mut Vec<Tree>,
     ) {
-        if let Some(peek) = iter.peek() {
+        let mut seen_els = false;
+        while let Some(peek) = iter.peek() {
+            let loc = peek.loc;
             match peek.token {
                 TokenType::Els => {
                     iter.next();
-                    if !els.is_empty() {
-                        Logger::error(
-                            "Unexpected els statements",
-                            iter.peek().unwrap().loc,
-                            ErrorType::Parsing,
-                        );
+                    if seen_els {
+                        self.logger
+                            .error("Unexpected els statements", loc, ErrorType::Parsing);
                     }
+                    seen_els = true;
                     *els = self.parse_block(iter);
-                    self.next_case(iter, els, els_ifs);
                 }
                 TokenType::ElsIf => {
                     iter.next();
+                    if seen_els {
+                        self.logger.error(
+                            "elsif can't come after els, move it before the els",
+                            loc,
+                            ErrorType::Parsing,
+                        );
+                    }
                     let expr = Box::new(self.parse_expression(iter));
                     let body = self.parse_block(iter);
-                    els_ifs.push(Tree::ElsIf { expr, body });
-                    self.next_case(iter, els, els_ifs);
+                    if !seen_els {
+                        els_ifs.push(Tree::ElsIf { expr, body });
+                    }
                 }
-                _ => (),
+                _ => break,
             }
         }
     }
@@ -330,7 +640,7 @@ impl Parser {
                 }
             }
         }
-        Logger::error(
+        self.logger.error(
             "Expected ] Or Items [..]",
             self.prev_token.loc,
             ErrorType::Parsing,
@@ -355,7 +665,7 @@ impl Parser {
                     }
                 }
             }
-            Logger::error(
+            self.logger.error(
                 "Expected ) Or Items (Args,..)",
                 self.prev_token.loc,
                 ErrorType::Parsing,
@@ -371,8 +681,9 @@ impl Parser {
         let mut fields = vec![];
         let mut methods = vec![];
 
-        while iter.peek().unwrap().token != TokenType::CloseCurly {
-            match iter.peek().unwrap().token {
+        while let Some(token) = self.peek_or_eof(iter) {
+            match token.token {
+                TokenType::CloseCurly => break,
                 TokenType::Let => {
                     fields.push(self.parse_factor(iter));
                 }
@@ -380,11 +691,11 @@ impl Parser {
                     methods.push(self.parse_factor(iter));
                 }
 
-                _ => Logger::error(
-                    "Unexpected Token",
-                    iter.peek().unwrap().loc,
-                    ErrorType::Parsing,
-                ),
+                _ => {
+                    self.logger
+                        .error("Unexpected Token", token.loc, ErrorType::Parsing);
+                    iter.next();
+                }
             };
         }
         iter.next();
@@ -392,32 +703,155 @@ impl Parser {
         (fields, methods)
     }
 
+    // The names of `let [a, b] = list`, after the `[`
+    fn parse_destructure(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
+        let mut names = vec![];
+        while let Some(token) = self.peek_or_eof(iter) {
+            if token.token == TokenType::CloseSquare {
+                break;
+            }
+            match self.expect_token(iter, TokenType::Ident(String::new())) {
+                Some(TokenType::Ident(name)) => names.push(name),
+                // Skip the bad token so the loop makes progress
+                _ => {
+                    iter.next();
+                }
+            }
+            if iter.peek().is_some_and(|t| t.token == TokenType::Comma) {
+                iter.next();
+            }
+        }
+        self.expect_token(iter, TokenType::CloseSquare);
+        if self.expect_token(iter, TokenType::Equal).is_none() {
+            return Tree::Empty();
+        }
+        Tree::Destructure(names, Box::new(self.parse_expression(iter)))
+    }
+
     fn parse_struct_fields(
         &mut self,
         iter: &mut Peekable<std::slice::Iter<Token>>,
     ) -> FxHashMap<String, Tree> {
         let mut map = FxHashMap::default();
-        while iter.peek().unwrap().token != TokenType::CloseCurly {
+        while let Some(token) = self.peek_or_eof(iter) {
+            if token.token == TokenType::CloseCurly {
+                break;
+            }
             if let Some(TokenType::Ident(field_name)) =
                 self.expect_token(iter, TokenType::Ident(String::new()))
             {
                 if self.expect_token(iter, TokenType::Colon).is_some() {
                     map.insert(field_name, self.parse_expression(iter));
                 }
-                if iter.peek().unwrap().token == TokenType::Comma {
+                if iter.peek().is_some_and(|t| t.token == TokenType::Comma) {
                     iter.next();
                     continue;
                 }
+            } else {
+                // Skip the bad token so the loop makes progress
+                iter.next();
             }
         }
         iter.next();
         map
     }
 
+    // Parses `"key": value, ...}` after the opening `{` of a map literal
+    fn parse_map_entries(
+        &mut self,
+        iter: &mut Peekable<std::slice::Iter<Token>>,
+    ) -> FxHashMap<String, Tree> {
+        let mut map = FxHashMap::default();
+        while let Some(item) = iter.peek() {
+            match item.token {
+                TokenType::CloseCurly => {
+                    iter.next();
+                    return map;
+                }
+                TokenType::Comma => {
+                    iter.next();
+                }
+                _ => {
+                    if let Some(TokenType::String(key)) =
+                        self.expect_token(iter, TokenType::String(String::new()))
+                    {
+                        let loc = self.prev_token.loc;
+                        if self.expect_token(iter, TokenType::Colon).is_some() {
+                            let value = self.parse_expression(iter);
+                            if map.insert(key.clone(), value).is_some() {
+                                self.logger.error(
+                                    &format!("Duplicate map key \"{key}\""),
+                                    loc,
+                                    ErrorType::Parsing,
+                                );
+                            }
+                        }
+                    } else {
+                        iter.next();
+                    }
+                }
+            }
+        }
+        self.logger.error(
+            "Expected } Or Entries {\"key\": value,..}",
+            self.prev_token.loc,
+            ErrorType::Parsing,
+        );
+        map
+    }
+
+    // The expression of one `{expr}` in a string
+    fn parse_interpolated(&self, tokens: &[Token], string: &Token) -> Tree {
+        // The lexer already reported why it is empty
+        if tokens.is_empty() {
+            return Tree::Empty();
+        }
+        let mut parser = Parser {
+            tokens: tokens.to_vec(),
+            prev_token: string.clone(),
+            logger: self.logger.clone(),
+        };
+        // The logger is shared, only errors from here on are about this string
+        let errors = parser.error_count();
+        let mut trees = parser.parse_tokens();
+        // An incomplete expression was reported while parsing it
+        if parser.error_count() != errors {
+            return Tree::Empty();
+        }
+        if trees.len() != 1 {
+            let loc = tokens.first().map_or(string.loc, |token| token.loc);
+            self.logger.error(
+                "Expected one expression in {..}, use \\{ for a literal brace",
+                loc,
+                ErrorType::Parsing,
+            );
+            return Tree::Empty();
+        }
+        trees.remove(0)
+    }
+
+    // Skips past the `}` closing a `{` that was already consumed, so one bad
+    // literal doesn't turn the rest of it into more errors
+    fn skip_braces(iter: &mut Peekable<std::slice::Iter<Token>>) {
+        let mut depth = 1;
+        for token in iter.by_ref() {
+            match token.token {
+                TokenType::OpenCurly => depth += 1,
+                TokenType::CloseCurly => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+
     fn parse_factor(&mut self, iter: &mut Peekable<std::slice::Iter<Token>>) -> Tree {
         if let Some(it) = iter.next() {
             match &it.token {
                 TokenType::Number(num) => Tree::Number(*num),
+                #[cfg(feature = "bignum")]
+                TokenType::BigInt(n) => Tree::BigInt(n.clone()),
                 TokenType::Bool(b) => Tree::Bool(*b),
                 TokenType::Null => Tree::Empty(),
                 TokenType::Bang => {
@@ -431,6 +865,7 @@ impl Parser {
                             return Tree::FnCall {
                                 name: string.to_string(),
                                 args,
+                                loc: it.loc,
                             };
                         }
                         if p.token == TokenType::OpenCurly {
@@ -440,17 +875,11 @@ impl Parser {
                                           // and the one after that should be a Colon.
                             let is_struct_syntax = clone
                                 .next()
-                                .map(|t| match &t.token {
-                                    TokenType::Ident(_) => true,
-                                    _ => false,
-                                })
+                                .map(|t| matches!(&t.token, TokenType::Ident(_)))
                                 .unwrap_or(false)
                                 && clone
                                     .next()
-                                    .map(|t| match &t.token {
-                                        TokenType::Colon => true,
-                                        _ => false,
-                                    })
+                                    .map(|t| matches!(&t.token, TokenType::Colon))
                                     .unwrap_or(false);
 
                             if is_struct_syntax {
@@ -464,23 +893,49 @@ impl Parser {
                             }
                         }
                     }
-                    Tree::Ident(string.to_string())
-                }
-                TokenType::String(string) => Tree::String(
-                    // i could use a crate for that  ig if i wanna use unicodes
-                    Box::new(
-                        string
-                            .to_string()
-                            .replace("\\n", "\n")
-                            .replace("\\t", "\t")
-                            .replace("\\r", "\r")
-                            .replace("\\\"", "\""),
-                    ),
-                ),
+                    Tree::Ident(string.to_string(), it.loc)
+                }
+                TokenType::String(string) => Tree::String(Box::new(unescape(string))),
+                TokenType::RawString(string) => Tree::String(Box::new(string.to_string())),
+                TokenType::InterpString(parts) => {
+                    let parts = parts
+                        .iter()
+                        .map(|part| match part {
+                            StringPart::Text(text) => Tree::String(Box::new(unescape(text))),
+                            StringPart::Expr(tokens) => self.parse_interpolated(tokens, it),
+                        })
+                        .collect();
+                    self.prev_token = it.clone();
+                    Tree::InterpString(parts)
+                }
                 TokenType::OpenSquare => {
                     let items = self.parse_items(iter);
                     Tree::List(items)
                 }
+                // Blocks are only parsed through `parse_block`, and struct init needs
+                // a leading `Ident`, so a bare `{` here is a map when it is empty or
+                // starts with a `"string":` key
+                TokenType::OpenCurly => {
+                    let mut clone = iter.clone();
+                    let is_map_syntax = match clone.next().map(|t| &t.token) {
+                        Some(TokenType::CloseCurly) => true,
+                        Some(TokenType::String(_)) => {
+                            matches!(clone.next().map(|t| &t.token), Some(TokenType::Colon))
+                        }
+                        _ => false,
+                    };
+                    if is_map_syntax {
+                        self.prev_token = it.clone();
+                        return Tree::Map(self.parse_map_entries(iter));
+                    }
+                    self.logger.error(
+                        "Map keys have to be strings like {\"key\": value}, struct init needs the struct name",
+                        it.loc,
+                        ErrorType::Parsing,
+                    );
+                    Self::skip_braces(iter);
+                    Tree::Empty()
+                }
                 TokenType::Plus => self.parse_factor(iter),
                 TokenType::Minus => {
                     let factor = self.parse_factor(iter);
@@ -488,14 +943,20 @@ impl Parser {
                         Box::new(Tree::Number(0.0)),
                         TokenType::Minus,
                         Box::new(factor),
+                        it.loc,
                     )
                 }
+                // A bare `ret` before `}` or the end returns null
                 TokenType::Ret => {
                     self.prev_token = it.clone();
-                    Tree::Ret(Box::new(self.parse_expression(iter)))
+                    match iter.peek().map(|t| &t.token) {
+                        None | Some(TokenType::CloseCurly) => Tree::Ret(Box::new(Tree::Empty())),
+                        Some(_) => Tree::Ret(Box::new(self.parse_expression(iter))),
+                    }
                 }
-                TokenType::OpenParen => match iter.peek().unwrap().token {
-                    TokenType::CloseParen => {
+                TokenType::OpenParen => match self.peek_or_eof(iter).map(|t| &t.token) {
+                    None => Tree::Empty(),
+                    Some(TokenType::CloseParen) => {
                         iter.next();
                         self.prev_token = it.clone();
                         let expr = Tree::Empty;
@@ -503,10 +964,10 @@ impl Parser {
                     }
                     _ => {
                         let expr = self.parse_expression(iter);
-                        match iter.next().unwrap().token {
-                            TokenType::CloseParen => expr,
+                        match iter.next().map(|t| &t.token) {
+                            Some(TokenType::CloseParen) => expr,
                             _ => {
-                                Logger::error(
+                                self.logger.error(
                                     "Expected closing parenthesis",
                                     it.loc,
                                     ErrorType::Parsing,
@@ -516,21 +977,19 @@ impl Parser {
                         }
                     }
                 },
-                TokenType::Let => match &iter.next().unwrap().token {
-                    TokenType::Ident(var) => {
-                        let next = *iter.peek().unwrap();
-                        match next.token {
-                            TokenType::Equal => {
-                                self.prev_token = next.clone();
-                                iter.next();
-                                let expr = self.parse_expression(iter);
-                                Tree::Let(var.to_string(), Box::new(expr))
-                            }
-                            _ => Tree::Let(var.to_string(), Box::new(Tree::Empty())),
+                TokenType::Let => match iter.next().map(|t| &t.token) {
+                    Some(TokenType::Ident(var)) => match iter.peek().copied() {
+                        Some(next) if next.token == TokenType::Equal => {
+                            self.prev_token = next.clone();
+                            iter.next();
+                            let expr = self.parse_expression(iter);
+                            Tree::Let(var.to_string(), Box::new(expr))
                         }
-                    }
+                        _ => Tree::Let(var.to_string(), Box::new(Tree::Empty())),
+                    },
+                    Some(TokenType::OpenSquare) => self.parse_destructure(iter),
                     _ => {
-                        Logger::error(
+                        self.logger.error(
                             "Expected identifier after 'let'",
                             it.loc,
                             ErrorType::Parsing,
@@ -552,15 +1011,30 @@ impl Parser {
                         els_ifs,
                     }
                 }
+                TokenType::Try => {
+                    let body = self.parse_block(iter);
+                    self.expect_token(iter, TokenType::Catch);
+                    let err_name = match self.expect_token(iter, TokenType::Ident(String::new())) {
+                        Some(TokenType::Ident(name)) => name,
+                        _ => String::new(),
+                    };
+                    let catch = self.parse_block(iter);
+                    self.prev_token = it.clone();
+                    Tree::Try {
+                        body,
+                        err_name,
+                        catch,
+                    }
+                }
                 TokenType::While => {
                     let expr = Box::new(self.parse_expression(iter));
                     let body = self.parse_block(iter);
                     self.prev_token = it.clone();
                     Tree::While { expr, body }
                 }
-                TokenType::For => match &iter.next().unwrap().token {
-                    TokenType::Ident(var) => match &iter.peek().unwrap().token {
-                        TokenType::ThinArrow => {
+                TokenType::For => match iter.next().map(|t| &t.token) {
+                    Some(TokenType::Ident(var)) => match iter.peek().map(|t| &t.token) {
+                        Some(TokenType::ThinArrow) => {
                             iter.next();
                             let expr = Box::new(self.parse_expression(iter));
                             let body = self.parse_block(iter);
@@ -572,12 +1046,12 @@ impl Parser {
                             }
                         }
                         _ => {
-                            Logger::error("Expected ->", it.loc, ErrorType::Parsing);
+                            self.logger.error("Expected ->", it.loc, ErrorType::Parsing);
                             Tree::Empty()
                         }
                     },
                     _ => {
-                        Logger::error(
+                        self.logger.error(
                             "Expected Var -> Expr..Expr or Var -> List",
                             it.loc,
                             ErrorType::Parsing,
@@ -586,10 +1060,26 @@ impl Parser {
                     }
                 },
                 TokenType::Fn => {
-                    if let Some(TokenType::Ident(name)) =
-                        self.expect_token(iter, TokenType::Ident(String::new()))
-                    {
+                    // `fn(args) => ..` without a name is an anonymous function
+                    let name = match iter.peek().map(|t| &t.token) {
+                        Some(TokenType::OpenParen) => Some(String::new()),
+                        _ => match self.expect_token(iter, TokenType::Ident(String::new())) {
+                            Some(TokenType::Ident(name)) => Some(name),
+                            _ => None,
+                        },
+                    };
+                    if let Some(name) = name {
                         let args = self.parse_args(iter);
+                        let rest = args.iter().position(|arg| matches!(arg, Tree::Rest(..)));
+                        if let Some(Tree::Rest(_, loc)) =
+                            rest.filter(|&i| i + 1 < args.len()).map(|i| &args[i])
+                        {
+                            self.logger.error(
+                                "A rest parameter has to be the last one",
+                                *loc,
+                                ErrorType::Parsing,
+                            );
+                        }
                         let mut body = vec![];
                         if self.expect_token(iter, TokenType::FatArrow).is_some() {
                             if let Some(next) = iter.peek() {
@@ -597,7 +1087,11 @@ impl Parser {
                                     TokenType::OpenCurly => {
                                         body = self.parse_block(iter);
                                     }
-                                    _ => body.push(self.parse_expression(iter)),
+                                    // `fn f(x) => expr` returns the value of `expr`
+                                    _ => match self.parse_expression(iter) {
+                                        expr @ Tree::Ret(_) => body.push(expr),
+                                        expr => body.push(Tree::Ret(Box::new(expr))),
+                                    },
                                 }
                             }
                         }
@@ -606,6 +1100,13 @@ impl Parser {
                     Tree::Empty()
                 }
 
+                TokenType::Ellipsis => {
+                    match self.expect_token(iter, TokenType::Ident(String::new())) {
+                        Some(TokenType::Ident(name)) => Tree::Rest(name, it.loc),
+                        _ => Tree::Empty(),
+                    }
+                }
+
                 TokenType::Struct => {
                     if let Some(TokenType::Ident(name)) =
                         self.expect_token(iter, TokenType::Ident(String::new()))
@@ -619,7 +1120,8 @@ impl Parser {
                             };
                         }
                     } else {
-                        Logger::error("Expected Struct Name", it.loc, ErrorType::Parsing);
+                        self.logger
+                            .error("Expected Struct Name", it.loc, ErrorType::Parsing);
                     }
                     Tree::Empty()
                 }
@@ -627,9 +1129,9 @@ impl Parser {
                 TokenType::Import => {
                     let path = Box::new(self.parse_expression(iter));
                     let mut alias = None;
-                    if iter.peek().is_some() && iter.peek().unwrap().token == TokenType::As {
+                    if iter.peek().is_some_and(|t| t.token == TokenType::As) {
                         iter.next();
-                        if let TokenType::Ident(ref name) = iter.peek().unwrap().token {
+                        if let Some(TokenType::Ident(name)) = iter.peek().map(|t| &t.token) {
                             iter.next();
                             alias = Some(name.to_string());
                         }
@@ -638,11 +1140,12 @@ impl Parser {
                 }
 
                 TokenType::Els | TokenType::ElsIf => {
-                    Logger::error("Expected If statement first", it.loc, ErrorType::Parsing);
+                    self.logger
+                        .error("Expected If statement first", it.loc, ErrorType::Parsing);
                     Tree::Empty()
                 }
                 _ => {
-                    Logger::error(
+                    self.logger.error(
                         &format!("Invalid Token {:?}", it.token),
                         it.loc,
                         ErrorType::Parsing,
@@ -651,7 +1154,7 @@ impl Parser {
                 }
             }
         } else {
-            Logger::error(
+            self.logger.error(
                 "Expected Statement",
                 self.prev_token.loc,
                 ErrorType::Parsing,
@@ -660,3 +1163,170 @@ impl Parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, Lexer, Object};
+
+    fn parse(src: &str) -> Vec<Tree> {
+        Parser::with_source(Lexer::new(src).tokenize(), src).parse_tokens()
+    }
+
+    // The trees and the errors lexing and parsing `src` reported
+    fn parse_with_errors(src: &str) -> (Vec<Tree>, usize) {
+        let mut lexer = Lexer::new(src);
+        let mut parser = Parser::with_source(lexer.tokenize(), src);
+        let trees = parser.parse_tokens();
+        (trees, lexer.error_count() + parser.error_count())
+    }
+
+    fn eval(src: &str) -> Object {
+        Interpreter::new(".".to_string(), None).eval_str(src)
+    }
+
+    #[test]
+    fn pipe_chains_three_functions() {
+        let src = "fn inc(x) => x + 1\nfn mul(x, n) => x * n\nfn neg(x) => 0 - x\n2 |> inc |> mul(4) |> neg";
+        assert_eq!(eval(src), Object::Number(-12.0));
+        // The chain is nested calls, innermost first
+        let Some(Tree::FnCall { name, args, .. }) = parse("a |> f |> g(1) |> h").pop() else {
+            panic!("expected a call");
+        };
+        assert_eq!(name, "h");
+        assert!(matches!(&args[0], Tree::FnCall { name, args, .. }
+            if name == "g" && matches!(&args[0], Tree::FnCall { name, .. } if name == "f")));
+    }
+
+    #[test]
+    fn pipe_binds_looser_than_other_operators() {
+        let prelude = "fn inc(x) => x + 1\nlet a = 1\n";
+        assert_eq!(
+            eval(&format!("{prelude}1 << 1 |> inc")),
+            Object::Number(3.0)
+        );
+        assert_eq!(
+            eval(&format!("{prelude}a == 1 |> to_string")),
+            Object::String(Box::new("true".to_string()))
+        );
+        assert_eq!(
+            eval(&format!("{prelude}a + 2 * 3 |> inc")),
+            Object::Number(8.0)
+        );
+        assert_eq!(
+            eval(&format!("{prelude}let b = a |> inc\nb")),
+            Object::Number(2.0)
+        );
+    }
+
+    #[test]
+    fn bare_ret_returns_null() {
+        assert!(matches!(
+            parse("ret").as_slice(),
+            [Tree::Ret(expr)] if **expr == Tree::Empty()
+        ));
+        assert_eq!(eval("fn f() => { ret }\nf()"), Object::Null);
+        let early = "fn g(n) => {\n    if n > 0 { ret }\n    ret n\n}\n";
+        assert_eq!(eval(&format!("{early}g(1)")), Object::Null);
+        assert_eq!(eval(&format!("{early}g(-1)")), Object::Number(-1.0));
+    }
+
+    #[test]
+    fn comment_and_whitespace_only_input_parses_to_nothing() {
+        assert!(parse("// only a comment\n").is_empty());
+        assert!(parse("").is_empty());
+        assert!(parse("  \n\t\n").is_empty());
+        assert_eq!(eval("// only a comment\n"), Object::Null);
+    }
+
+    #[test]
+    fn step_is_only_a_clause_before_a_value() {
+        assert!(matches!(
+            parse("0..10 step 2").as_slice(),
+            [Tree::Range(_, _, Some(_))]
+        ));
+        assert!(matches!(
+            parse("0..10 step -n").as_slice(),
+            [Tree::Range(_, _, Some(_))]
+        ));
+        assert_eq!(
+            eval("let step = 1\nlet r = 0..5\nstep = 2\nstep"),
+            Object::Number(2.0)
+        );
+        assert_eq!(
+            eval("let step = 3\nlet r = 0..9 step step\nr"),
+            Object::Range(0.0, 9.0, 3.0)
+        );
+    }
+
+    #[test]
+    fn elsif_branches_run_in_source_order() {
+        let prelude = "let seen = []\nfn check(n, hit) => {\n    seen.push(n)\n    ret hit\n}\n";
+        let chain = "if check(0, false) { 0 } elsif check(1, false) { 1 } elsif check(2, true) { 2 } elsif check(3, true) { 3 } els { 4 }\n";
+        assert_eq!(
+            eval(&format!("{prelude}{chain}seen")),
+            Object::List(
+                vec![0.0, 1.0, 2.0]
+                    .into_iter()
+                    .map(Object::Number)
+                    .collect()
+            )
+        );
+        assert_eq!(
+            eval(&format!("{prelude}let r = 9\nif false {{ r = 0 }} elsif false {{ r = 1 }} els {{ r = 2 }}\nr")),
+            Object::Number(2.0)
+        );
+    }
+
+    #[test]
+    fn elsif_after_els_is_rejected() {
+        let (trees, errors) = parse_with_errors("if a { 1 } els { 2 } elsif b { 3 }");
+        assert!(errors > 0);
+        let [Tree::If { els_ifs, .. }] = trees.as_slice() else {
+            panic!("expected an if");
+        };
+        assert!(els_ifs.is_empty());
+    }
+
+    #[test]
+    fn incomplete_interpolated_expressions_are_errors() {
+        for src in [
+            "\"a {x +} b\"",
+            "\"a {x *} b\"",
+            "\"a {x ==} b\"",
+            "\"a {(x} b\"",
+        ] {
+            let (trees, errors) = parse_with_errors(src);
+            assert!(errors > 0, "no error for {src}");
+            let [Tree::InterpString(parts)] = trees.as_slice() else {
+                panic!("expected an interpolated string for {src}");
+            };
+            assert_eq!(parts[1], Tree::Empty(), "{src}");
+        }
+        let (trees, errors) = parse_with_errors("\"a {x + 1} b\"");
+        assert_eq!(errors, 0);
+        assert!(
+            matches!(trees.as_slice(), [Tree::InterpString(parts)] if parts[1] != Tree::Empty())
+        );
+    }
+
+    #[test]
+    fn bitwise_and_shift_sit_between_comparison_and_additive() {
+        let cases = [
+            ("1 | 2 == 3", Object::Bool(true)),
+            ("6 & 3 == 2", Object::Bool(true)),
+            ("1 | 6 ^ 3", Object::Number(5.0)),
+            ("6 ^ 3 & 2", Object::Number(4.0)),
+            ("1 << 2 & 4", Object::Number(4.0)),
+            ("1 + 1 << 2", Object::Number(8.0)),
+            ("2 * 3 << 1", Object::Number(12.0)),
+            // Every level is left associative
+            ("16 >> 2 >> 1", Object::Number(2.0)),
+            ("10 - 4 - 3", Object::Number(3.0)),
+            ("2 < 3 == true", Object::Bool(true)),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(eval(src), expected, "{src}");
+        }
+    }
+}
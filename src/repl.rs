@@ -0,0 +1,286 @@
+use iok::{Interpreter, Lexer, Object, Parser};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Config, Context, EditMode, Editor, Helper};
+use std::{env, fs, path::PathBuf};
+
+const HISTORY_FILE: &str = ".iok_history";
+const HISTORY_SIZE: usize = 1000;
+
+// Prompts and result format, each one can be changed with an environment
+// variable. `IOK_VERBOSE` set to anything but 0 also prints result types
+struct ReplConfig {
+    prompt: String,
+    continuation: String,
+    result_prefix: String,
+    verbose: bool,
+}
+
+impl ReplConfig {
+    fn from_env() -> ReplConfig {
+        let var = |name: &str, default: &str| env::var(name).unwrap_or(default.to_string());
+        ReplConfig {
+            prompt: var("IOK_PROMPT", ">"),
+            continuation: var("IOK_CONTINUATION_PROMPT", ".."),
+            result_prefix: var("IOK_RESULT_PREFIX", "-> "),
+            verbose: env::var("IOK_VERBOSE").is_ok_and(|v| !v.is_empty() && v != "0"),
+        }
+    }
+}
+
+// Completes identifiers from the names in the interpreter scopes
+struct ReplHelper {
+    names: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let prefix = &line[start..pos];
+        if prefix.is_empty() {
+            return Ok((pos, vec![]));
+        }
+
+        let candidates = self
+            .names
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+impl Highlighter for ReplHelper {}
+impl Validator for ReplHelper {}
+impl Helper for ReplHelper {}
+
+// Ctrl-C while a line runs stops it and goes back to the prompt. While
+// rustyline reads a line the terminal is in raw mode and Ctrl-C arrives as a
+// key instead, so this only sees interrupts during evaluation
+#[cfg(unix)]
+fn handle_interrupts() {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        Interpreter::interrupt();
+    }
+    // SAFETY: the handler only stores to an atomic, which is signal safe
+    unsafe {
+        libc::signal(libc::SIGINT, on_sigint as *const () as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+fn handle_interrupts() {}
+
+fn history_path() -> Option<PathBuf> {
+    env::home_dir().map(|home| home.join(HISTORY_FILE))
+}
+
+// How many more brackets are opened than closed outside strings and
+// comments, a line with some still open continues on the next one
+fn open_brackets(src: &str) -> i32 {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            '/' if !in_string && chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            '{' | '(' | '[' if !in_string => depth += 1,
+            '}' | ')' | ']' if !in_string => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+// One entry, more lines are read with the continuation prompt while
+// brackets are left open
+fn read_entry(
+    editor: &mut Editor<ReplHelper, DefaultHistory>,
+    config: &ReplConfig,
+) -> Result<String, ReadlineError> {
+    let mut input = editor.readline(&config.prompt)?;
+    while open_brackets(&input) > 0 {
+        let line = editor.readline(&config.continuation)?;
+        input.push('\n');
+        input.push_str(&line);
+    }
+    Ok(input)
+}
+
+// Parses and runs one entry then prints its value or error, returns true if
+// it ran without errors
+fn run_entry(interpreter: &mut Interpreter, config: &ReplConfig, input: &str) -> bool {
+    // A line with syntax errors is not run, the errors are already printed
+    let mut lexer = Lexer::new(input);
+    let mut parser = Parser::with_source(lexer.tokenize(), input);
+    let ast = parser.parse_tokens();
+    if lexer.error_count() + parser.error_count() != 0 {
+        return false;
+    }
+    // Comment-only lines parse to nothing, there is no value to print
+    if ast.is_empty() {
+        return false;
+    }
+
+    Interpreter::clear_interrupt();
+    let mut obj = Object::Null;
+    for stmt in &ast {
+        obj = interpreter.interpret(stmt);
+        if let Object::Error { .. } = obj {
+            break;
+        }
+    }
+    if let Object::Error { .. } = obj {
+        interpreter.report_error(&obj);
+        return false;
+    }
+    if config.verbose {
+        println!("{}{obj} ({})", config.result_prefix, obj.type_name());
+    } else {
+        println!("{}{obj}", config.result_prefix);
+    }
+    true
+}
+
+// `:save FILE` writes the session so far as a script, `:load FILE` runs a
+// script in the session
+fn run_command(
+    interpreter: &mut Interpreter,
+    config: &ReplConfig,
+    session: &mut Vec<String>,
+    command: &str,
+) {
+    let (name, path) = command.split_once(' ').unwrap_or((command, ""));
+    let path = path.trim();
+    match name {
+        "save" | "load" if path.is_empty() => eprintln!("Expected a file after :{name}"),
+        "save" => {
+            let mut script = session.join("\n");
+            script.push('\n');
+            match fs::write(path, script) {
+                Ok(()) => println!("Saved {} entries to {path}", session.len()),
+                Err(err) => eprintln!("Can't write {path}: {err}"),
+            }
+        }
+        "load" => match fs::read_to_string(path) {
+            Ok(source) => {
+                if run_entry(interpreter, config, &source) {
+                    session.push(source.trim_end().to_string());
+                }
+            }
+            Err(err) => eprintln!("Can't open file {path}: {err}"),
+        },
+        _ => eprintln!("Unknown command :{name}, try :save FILE or :load FILE"),
+    }
+}
+
+pub fn interpret_mode(interpreter: &mut Interpreter) {
+    let config = ReplConfig::from_env();
+    let editor_config = Config::builder()
+        .max_history_size(HISTORY_SIZE)
+        .expect("Invalid history size")
+        .history_ignore_dups(true)
+        .expect("Invalid history config")
+        .auto_add_history(true)
+        .edit_mode(EditMode::Emacs)
+        .build();
+    let mut editor: Editor<ReplHelper, DefaultHistory> =
+        Editor::with_config(editor_config).expect("Can't start the REPL");
+    editor.set_helper(Some(ReplHelper { names: vec![] }));
+
+    handle_interrupts();
+
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing or unreadable history file just starts an empty history
+        let _ = editor.load_history(path);
+    }
+
+    // Entries that ran without errors, what `:save` writes
+    let mut session: Vec<String> = vec![];
+    loop {
+        if let Some(helper) = editor.helper_mut() {
+            helper.names = interpreter.scope_names();
+        }
+
+        let input = match read_entry(&mut editor, &config) {
+            Ok(line) => {
+                if let Some(path) = &history {
+                    if let Err(err) = editor.save_history(path) {
+                        eprintln!("Can't save history to {}: {err}", path.display());
+                    }
+                }
+                line.trim_end().to_string()
+            }
+            Err(ReadlineError::Interrupted) => continue,
+            // Ctrl-D on an empty line, rustyline already ended the line
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("Can't read input: {err}");
+                break;
+            }
+        };
+
+        if input.is_empty() {
+            continue;
+        }
+
+        if let Some(command) = input.strip_prefix(':') {
+            run_command(interpreter, &config, &mut session, command);
+        } else if run_entry(interpreter, &config, &input) {
+            session.push(input);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ReplConfig {
+        ReplConfig {
+            prompt: ">".to_string(),
+            continuation: "..".to_string(),
+            result_prefix: "-> ".to_string(),
+            verbose: false,
+        }
+    }
+
+    // Entries that parse to nothing used to crash on `ast.last().unwrap()`
+    #[test]
+    fn comment_only_entry_runs_nothing() {
+        let mut interpreter = Interpreter::new(".".to_string(), None);
+        assert!(!run_entry(&mut interpreter, &config(), "// comment"));
+        assert!(!run_entry(&mut interpreter, &config(), "   "));
+        assert!(run_entry(
+            &mut interpreter,
+            &config(),
+            "let x = 1 // comment"
+        ));
+    }
+}
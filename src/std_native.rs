@@ -1,8 +1,304 @@
 use crate::interpreter::Interpreter;
-use crate::object::Object;
+use crate::json;
+use crate::object::{to_int, Object, Shared};
+use lazy_static::lazy_static;
+use rustc_hash::FxHashMap;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub type NativeFn = fn(Vec<Object>, &mut Interpreter) -> Object;
 
+lazy_static! {
+    // Signature and description of the builtin functions and methods for `help`
+    static ref HELP: HashMap<&'static str, (&'static str, &'static str)> = {
+        let mut map = HashMap::new();
+        map.insert("write", ("write(args..)", "Prints the arguments without a newline"));
+        map.insert("writeln", ("writeln(args..)", "Prints the arguments then a newline"));
+        map.insert(
+            "format",
+            ("format(template, args..)", "Replaces each {} with the next arg, {{ and }} are braces and {{x}} isn't interpolated"),
+        );
+        map.insert(
+            "is_prime",
+            ("is_prime(n)", "Whether the whole number n >= 1 is prime"),
+        );
+        map.insert(
+            "factorize",
+            ("factorize(n)", "Prime factors of n >= 1 with repeats, factorize(12) is [2, 2, 3]"),
+        );
+        map.insert("input", ("input(prompt?)", "Reads a line from stdin, null on EOF"));
+        map.insert("read_file", ("read_file(path)", "Returns the file contents, an error if it can't be read"));
+        map.insert(
+            "write_file",
+            ("write_file(path, contents)", "Writes the file, returns true or an error"),
+        );
+        map.insert("exit", ("exit(code?)", "Exits the program with the code"));
+        map.insert(
+            "mem_report",
+            ("mem_report()", "Map with the scope depth, variable and object counts"),
+        );
+        map.insert("help", ("help(name?)", "Describes a builtin or a function"));
+        map.insert("type", ("type(value)", "Name of the runtime type of the value"));
+        map.insert(
+            "describe",
+            ("describe(value)", "Map with the type, display and parts of a value"),
+        );
+        map.insert(
+            "try_number",
+            ("try_number(value)", "[true, number] for numbers and numeric strings, [false, null] otherwise"),
+        );
+        map.insert(
+            "try_int",
+            ("try_int(value)", "Like try_number but fails on fractions and numbers past the integer range"),
+        );
+        map.insert(
+            "try_bool",
+            ("try_bool(value)", "[true, bool] for bools and \"true\"/\"false\", [false, null] otherwise"),
+        );
+        map.insert(
+            "currency",
+            ("currency(n, symbol)", "n with thousands separators and two decimals after symbol, \"$1,234.50\""),
+        );
+        map.insert(
+            "percent",
+            ("percent(n)", "Fraction n as a percentage with two decimals, 0.125 gives \"12.50%\""),
+        );
+        map.insert(
+            "date_now",
+            ("date_now()", "Map with the UTC year, month, day, hour, minute, second and timestamp"),
+        );
+        map.insert(
+            "format_date",
+            ("format_date(timestamp, pattern?)", "Formats epoch seconds in UTC, %Y %m %d %H %M %S and %% are replaced"),
+        );
+        map.insert(
+            "after",
+            ("after(ms, fn)", "Schedules fn to be called by run_timers once ms milliseconds passed"),
+        );
+        map.insert(
+            "run_timers",
+            ("run_timers()", "Calls the due timers, returns how many are still pending or the first error"),
+        );
+        map.insert(
+            "make",
+            ("make(Struct, map)", "Instance with the fields from map, missing fields keep their defaults"),
+        );
+        map.insert("from", ("Struct::from(map)", "Same as make(Struct, map)"));
+        map.insert(
+            "json_stringify",
+            ("json_stringify(value)", "JSON text of the value, instances get a \"__type\" key with the struct name"),
+        );
+        map.insert(
+            "json_parse",
+            ("json_parse(text, structs?)", "Value of the JSON text, objects tagged with one of the structs become instances"),
+        );
+        map.insert(
+            "deep_equal",
+            ("deep_equal(a, b)", "Compares nested lists, maps and instances item by item"),
+        );
+        map.insert(
+            "approx",
+            ("approx(a, b, eps?)", "deep_equal where numbers may differ by eps, 1e-9 by default"),
+        );
+        map.insert(
+            "math",
+            ("math.name(..)", "Math functions and the constants math.pi and math.e"),
+        );
+        map.insert("math.sqrt", ("math.sqrt(n)", "Square root of n"));
+        map.insert("math.pow", ("math.pow(n, exp)", "n raised to exp"));
+        map.insert(
+            "math.floor_div",
+            ("math.floor_div(a, b)", "a / b rounded down, math.floor_div(-7, 2) is -4"),
+        );
+        map.insert("math.factorial", ("math.factorial(n)", "n!, 1 * 2 * .. * n"));
+        map.insert(
+            "math.comb",
+            ("math.comb(n, k)", "Ways to choose k of n items ignoring order, 0 if k > n"),
+        );
+        map.insert(
+            "math.perm",
+            ("math.perm(n, k)", "Ways to choose k of n items in order, 0 if k > n"),
+        );
+        map.insert("math.abs", ("math.abs(n)", "Absolute value of n"));
+        map.insert("math.floor", ("math.floor(n)", "Largest integer not above n"));
+        map.insert("math.ceil", ("math.ceil(n)", "Smallest integer not below n"));
+        map.insert("math.round", ("math.round(n)", "Nearest integer, halves round away from zero"));
+        map.insert("math.sin", ("math.sin(n)", "Sine of n radians"));
+        map.insert("math.cos", ("math.cos(n)", "Cosine of n radians"));
+        map.insert(
+            "matrix",
+            ("matrix.name(..)", "Matrix functions on lists of rows, invalid for bad shapes"),
+        );
+        map.insert("matrix.multiply", ("matrix.multiply(a, b)", "Matrix product a * b"));
+        map.insert("matrix.transpose", ("matrix.transpose(m)", "Rows turned into columns"));
+        map.insert(
+            "matrix.identity",
+            ("matrix.identity(n)", "n by n identity matrix, n up to 1024"),
+        );
+        map.insert(
+            "matrix.determinant",
+            ("matrix.determinant(m)", "Determinant of a square matrix"),
+        );
+        map.insert("log", ("log.name(..)", "Timestamped messages on stderr, see log.set_level"));
+        map.insert("log.debug", ("log.debug(args..)", "Logs at debug level"));
+        map.insert("log.info", ("log.info(args..)", "Logs at info level"));
+        map.insert("log.warn", ("log.warn(args..)", "Logs at warn level"));
+        map.insert("log.error", ("log.error(args..)", "Logs at error level"));
+        map.insert(
+            "log.set_level",
+            ("log.set_level(name)", "Drops messages below debug, info, warn or error, info by default"),
+        );
+        map.insert("log.level", ("log.level()", "Name of the current log level"));
+        map.insert(
+            "random",
+            ("random(a?, b?)", "Random number in [0, 1), or in [a, b) when given"),
+        );
+        map.insert(
+            "random_int",
+            ("random_int(min, max)", "Random integer between min and max, both included"),
+        );
+        map.insert(
+            "freeze",
+            ("freeze(value)", "Immutable list, map or instance, changing it raises an error"),
+        );
+        map.insert("clock", ("clock()", "Seconds since the interpreter started, for timing code"));
+        map.insert("sleep", ("sleep(seconds)", "Blocks for the given number of seconds"));
+        map.insert(
+            "to_number",
+            ("to_number(value)", "Number of the value, invalid for strings that aren't numbers"),
+        );
+        map.insert("to_string", ("to_string(value)", "The value as the text write prints"));
+        map.insert(
+            "ref",
+            ("ref(value)", "Shared mutable cell holding value, copies of it share the cell"),
+        );
+        map.insert("deref", ("deref(cell)", "Copy of the value in the cell"));
+        map.insert(
+            "set",
+            ("set(cell, value)", "Stores value in the cell, every copy of the ref sees it"),
+        );
+        map.insert(
+            "assert_throws",
+            (
+                "assert_throws(fn, expected?)",
+                "Fails unless fn() raises an error containing expected, returns its message",
+            ),
+        );
+        map.insert(
+            "get",
+            ("map.get(key, default?)", "Value at key of a map or instance, default when missing"),
+        );
+        map.insert("entries", ("map.entries()", "List of [key, value] pairs sorted by key"));
+        map.insert(
+            "from_entries",
+            ("from_entries(pairs)", "Map from a list of [key, value] pairs"),
+        );
+        map.insert(
+            "merge",
+            ("map.merge(other)", "New map with the entries of both, other wins (shallow)"),
+        );
+        map.insert("to_json", ("instance.to_json()", "json_stringify of the instance"));
+        map.insert(
+            "from_json",
+            ("Struct::from_json(text)", "Instance from a JSON object with exactly the struct's fields"),
+        );
+        map.insert("heap", ("heap()", "New empty priority queue"));
+        map.insert(
+            "heap.push",
+            ("h.push(value, priority?)", "Adds value, priority defaults to value"),
+        );
+        map.insert(
+            "heap.pop_min",
+            ("h.pop_min()", "Removes the lowest priority value, null if empty"),
+        );
+        map.insert(
+            "heap.pop_max",
+            ("h.pop_max()", "Removes the highest priority value, null if empty"),
+        );
+        map.insert("heap.peek_min", ("h.peek_min()", "Lowest priority value without removing it"));
+        map.insert("heap.peek_max", ("h.peek_max()", "Highest priority value without removing it"));
+        map.insert("heap.to_list", ("h.to_list()", "Values from lowest to highest priority"));
+        map.insert("len", ("value.len()", "Length of a string or list"));
+        map.insert("push", ("value.push(item)", "Appends to a string or list"));
+        map.insert("pop", ("value.pop()", "Removes and returns the last item, an error if empty"));
+        map.insert("min_by", ("list.min_by(fn)", "Element with the smallest fn(element)"));
+        map.insert("max_by", ("list.max_by(fn)", "Element with the largest fn(element)"));
+        map.insert("sum_by", ("list.sum_by(fn)", "Sum of fn(element) over the list"));
+        map.insert("product", ("list.product()", "Product of the numbers in the list"));
+        map.insert("map", ("list.map(fn)", "New list of fn(element) for each element"));
+        map.insert("filter", ("list.filter(fn)", "New list of the elements where fn is true"));
+        map.insert(
+            "reduce",
+            ("list.reduce(fn, init)", "Folds from the start calling fn(acc, element)"),
+        );
+        map.insert("sort", ("list.sort(cmp?)", "Sorted copy, cmp(a, b) < 0 puts a first"));
+        map.insert("reverse", ("value.reverse()", "Reversed copy of a string or list"));
+        map.insert("enqueue", ("list.enqueue(item)", "Adds item to the back of the queue"));
+        map.insert(
+            "dequeue",
+            ("list.dequeue()", "Removes and returns the first item, an error if empty"),
+        );
+        map.insert("peek", ("list.peek()", "First item without removing it, an error if empty"));
+        map.insert(
+            "peek_back",
+            ("list.peek_back()", "Last item without removing it, an error if empty"),
+        );
+        map.insert("head", ("list.head()", "First element, an error for an empty list"));
+        map.insert("tail", ("list.tail()", "New list of everything after the first element"));
+        map.insert("prepend", ("list.prepend(item)", "New list with item in front"));
+        map.insert("join", ("list.join(sep)", "Joins the elements as strings with sep"));
+        map.insert(
+            "split",
+            ("string.split(sep)", "List of the parts between sep, chars if sep is empty"),
+        );
+        map.insert(
+            "contains",
+            (
+                "list.contains(x) / string.contains(part)",
+                "True if an element equals x, or part is a substring",
+            ),
+        );
+        map.insert(
+            "index_of",
+            (
+                "list.index_of(x) / string.index_of(part)",
+                "Index of the first match in chars for strings, null if none",
+            ),
+        );
+        map.insert("upper", ("string.upper()", "Uppercase copy"));
+        map.insert("lower", ("string.lower()", "Lowercase copy"));
+        map.insert(
+            "replace",
+            ("string.replace(from, to)", "Copy with every from replaced by to"),
+        );
+        map.insert(
+            "each",
+            ("range.each(fn)", "Calls fn(n) for every number of the range"),
+        );
+        map.insert(
+            "intersect",
+            ("range.intersect(other)", "Range both cover, null if they don't overlap"),
+        );
+        map.insert(
+            "union",
+            ("range.union(other)", "Smallest range covering both, even if there's a gap"),
+        );
+        map.insert("trim", ("string.trim()", "Copy without leading and trailing whitespace"));
+        map.insert(
+            "code_at",
+            ("string.code_at(i)", "Code point of the char at index i, null if out of range"),
+        );
+        map.insert(
+            "reduce_right",
+            ("list.reduce_right(init, fn)", "Folds from the end calling fn(element, acc)"),
+        );
+        map
+    };
+}
+
 pub fn native_write(args: Vec<Object>, _: &mut Interpreter) -> Object {
     for arg in args {
         print!("{}", arg);
@@ -10,17 +306,1182 @@ pub fn native_write(args: Vec<Object>, _: &mut Interpreter) -> Object {
     Object::Null
 }
 
+pub fn native_writeln(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    native_write(args, vm);
+    println!();
+    Object::Null
+}
+
+// `format("{} + {} = {}", 1, 2, 3)`, each `{}` takes the next argument printed
+// like `write` does. `{{` and `}}` are literal braces
+pub fn native_format(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let template = match args.first() {
+        Some(Object::String(s)) => s,
+        Some(other) => {
+            return Object::error(format!(
+                "format expects a string found {}",
+                other.type_name()
+            ))
+        }
+        None => return Object::error("Expected at least 1 arg found 0"),
+    };
+    let values = &args[1..];
+    let mut out = String::new();
+    let mut used = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                out.push(c);
+                chars.next();
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                if let Some(value) = values.get(used) {
+                    out.push_str(&value.to_string());
+                }
+                used += 1;
+            }
+            ('{' | '}', _) => {
+                return Object::error(format!("Unmatched {c} in format string, use {c}{c}"))
+            }
+            _ => out.push(c),
+        }
+    }
+    if used != values.len() {
+        return Object::error(format!(
+            "format string has {used} placeholders, expected as many args found {}",
+            values.len()
+        ));
+    }
+    Object::String(Box::new(out))
+}
+
+pub fn native_input(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    // `print!` would panic when stdout is closed
+    let mut stdout = io::stdout();
+    let prompt = args.first().map_or(String::new(), Object::to_string);
+    if let Err(err) = write!(stdout, "{prompt}").and_then(|_| stdout.flush()) {
+        return Object::error(format!("Can't write the input prompt: {err}"));
+    }
+
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => Object::Null,
+        Ok(_) => {
+            let trimmed_len = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(trimmed_len);
+            Object::String(Box::new(line))
+        }
+    }
+}
+
+pub fn native_read_file(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    if let Some(Object::String(path)) = args.first() {
+        return match fs::read_to_string(vm.resolve_path(path)) {
+            Ok(contents) => Object::String(Box::new(contents)),
+            Err(err) => Object::error(format!("Can't read file {path}: {err}")),
+        };
+    }
+    Object::error("read_file expects a path")
+}
+
+pub fn native_write_file(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    if let (Some(Object::String(path)), Some(contents)) = (args.first(), args.get(1)) {
+        return match fs::write(vm.resolve_path(path), contents.to_string()) {
+            Ok(_) => Object::Bool(true),
+            Err(err) => Object::error(format!("Can't write file {path}: {err}")),
+        };
+    }
+    Object::error("write_file expects a path and contents")
+}
+
+pub fn native_type(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let obj = args.first().unwrap_or(&Object::Null);
+    Object::String(Box::new(obj.type_name().to_string()))
+}
+
+// `[success, value]` pair returned by the `try_` conversions
+fn try_result(value: Option<Object>) -> Object {
+    match value {
+        Some(value) => Object::List(vec![Object::Bool(true), value]),
+        None => Object::List(vec![Object::Bool(false), Object::Null]),
+    }
+}
+
+// Unlike `to_number_obj` nothing is coerced, only numbers and strings holding
+// a finite number convert
+fn strict_number(obj: &Object) -> Option<f64> {
+    match obj {
+        Object::Number(n) => Some(*n),
+        Object::String(s) => s.trim().parse::<f64>().ok().filter(|n| n.is_finite()),
+        _ => None,
+    }
+}
+
+pub fn native_try_number(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let obj = args.first().unwrap_or(&Object::Null);
+    try_result(strict_number(obj).map(Object::Number))
+}
+
+pub fn native_try_int(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let obj = args.first().unwrap_or(&Object::Null);
+    let int = strict_number(obj)
+        .filter(|n| n.fract() == 0.0)
+        .and_then(to_int);
+    try_result(int.map(|n| Object::Number(n as f64)))
+}
+
+pub fn native_try_bool(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let value = match args.first() {
+        Some(Object::Bool(b)) => Some(*b),
+        Some(Object::String(s)) => match s.trim() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    };
+    try_result(value.map(Object::Bool))
+}
+
+// Two decimals with `,` between the thousands, the sign goes in front of `prefix`
+fn format_grouped(n: f64, prefix: &str) -> String {
+    let fixed = format!("{:.2}", n.abs());
+    let (int_part, frac_part) = fixed.split_once('.').unwrap_or((&fixed, "00"));
+
+    let mut grouped = String::new();
+    for (i, digit) in int_part.chars().enumerate() {
+        if i > 0 && (int_part.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    // Keep `-0.00` from showing up for tiny negatives
+    let sign = if n < 0.0 && fixed.bytes().any(|b| b.is_ascii_digit() && b != b'0') {
+        "-"
+    } else {
+        ""
+    };
+    format!("{sign}{prefix}{grouped}.{frac_part}")
+}
+
+pub fn native_currency(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match (args.first(), args.get(1)) {
+        (Some(Object::Number(n)), symbol) if n.is_finite() => {
+            let symbol = symbol.map_or(String::new(), |s| s.to_string());
+            Object::String(Box::new(format_grouped(*n, &symbol)))
+        }
+        _ => Object::Null,
+    }
+}
+
+pub fn native_percent(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match args.first() {
+        Some(Object::Number(n)) if n.is_finite() => {
+            Object::String(Box::new(format_grouped(n * 100.0, "") + "%"))
+        }
+        _ => Object::Null,
+    }
+}
+
+// UTC calendar fields of a unix timestamp: year, month, day, hour, minute, second.
+// Civil from days algorithm by Howard Hinnant, valid for the proleptic
+// Gregorian calendar
+fn civil_from_timestamp(timestamp: i64) -> (i64, i64, i64, i64, i64, i64) {
+    let days = timestamp.div_euclid(86_400);
+    let secs = timestamp.rem_euclid(86_400);
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day, secs / 3600, secs % 3600 / 60, secs % 60)
+}
+
+fn now_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+pub fn native_date_now(_: Vec<Object>, _: &mut Interpreter) -> Object {
+    let timestamp = now_timestamp();
+    let (year, month, day, hour, minute, second) = civil_from_timestamp(timestamp);
+
+    let mut date = FxHashMap::default();
+    for (name, value) in [
+        ("year", year),
+        ("month", month),
+        ("day", day),
+        ("hour", hour),
+        ("minute", minute),
+        ("second", second),
+        ("timestamp", timestamp),
+    ] {
+        date.insert(name.to_string(), Object::Number(value as f64));
+    }
+    Object::Map(date)
+}
+
+pub fn native_format_date(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let timestamp = match args.first().and_then(|n| n.get_int_value()) {
+        Some(timestamp) => timestamp,
+        None => return Object::Null,
+    };
+    let pattern = match args.get(1) {
+        Some(Object::String(pattern)) => pattern.to_string(),
+        _ => String::from("%Y-%m-%d %H:%M:%S"),
+    };
+    let (year, month, day, hour, minute, second) = civil_from_timestamp(timestamp);
+
+    let mut formatted = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => formatted += &format!("{year:04}"),
+            Some('m') => formatted += &format!("{month:02}"),
+            Some('d') => formatted += &format!("{day:02}"),
+            Some('H') => formatted += &format!("{hour:02}"),
+            Some('M') => formatted += &format!("{minute:02}"),
+            Some('S') => formatted += &format!("{second:02}"),
+            Some('%') => formatted.push('%'),
+            // Unknown specifiers are kept as they are
+            Some(other) => {
+                formatted.push('%');
+                formatted.push(other);
+            }
+            None => formatted.push('%'),
+        }
+    }
+    Object::String(Box::new(formatted))
+}
+
+pub fn native_after(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    match (args.first(), args.get(1)) {
+        (
+            Some(Object::Number(ms)),
+            Some(callback @ (Object::Fn { .. } | Object::NativeFn { .. })),
+        ) => {
+            let added = Duration::try_from_secs_f64(ms.max(0.0) / 1000.0)
+                .is_ok_and(|delay| vm.add_timer(delay, callback.clone()));
+            if added {
+                Object::Null
+            } else {
+                Object::error(format!("after can't wait for {ms} ms"))
+            }
+        }
+        _ => Object::error("after expects a delay in ms and a function"),
+    }
+}
+
+// Timers added by the callbacks only run on a later call. A callback that
+// fails stops the run, the due timers after it stay pending
+pub fn native_run_timers(_: Vec<Object>, vm: &mut Interpreter) -> Object {
+    let mut due = vm.take_due_timers().into_iter();
+    while let Some((_, callback)) = due.next() {
+        let result = vm.call_function_with(&callback, vec![], None);
+        if let Object::Error { .. } = result {
+            vm.restore_timers(due);
+            return result;
+        }
+    }
+    Object::Number(vm.pending_timers() as f64)
+}
+
+// Calls `f` with no args and fails unless it raises an error, `expected` has
+// to be part of the message when given. Gives back the message of the error
+pub fn native_assert_throws(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    let (function, expected) = match args.as_slice() {
+        [f @ (Object::Fn { .. } | Object::NativeFn { .. })] => (f, None),
+        [f @ (Object::Fn { .. } | Object::NativeFn { .. }), Object::String(expected)] => {
+            (f, Some(expected.as_str()))
+        }
+        _ => return Object::error("assert_throws expects a function and an optional message"),
+    };
+    match vm.call_function_with(function, vec![], None) {
+        Object::Error { msg, .. } => match expected {
+            Some(expected) if !msg.contains(expected) => Object::error(format!(
+                "assert_throws: expected an error containing \"{expected}\" but got \"{msg}\""
+            )),
+            _ => Object::String(msg),
+        },
+        value => Object::error(format!(
+            "assert_throws: expected an error but the function returned {value}"
+        )),
+    }
+}
+
+pub fn native_make(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match (args.first(), args.get(1)) {
+        (Some(def @ Object::StructDef { .. }), Some(Object::Map(values))) => {
+            def.instance_from(values)
+        }
+        _ => Object::error("make expects a struct and a map"),
+    }
+}
+
+pub fn native_json_stringify(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match json::stringify(args.first().unwrap_or(&Object::Null)) {
+        Ok(text) => Object::String(Box::new(text)),
+        Err(msg) => Object::error(msg),
+    }
+}
+
+pub fn native_json_parse(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let text = match args.first() {
+        Some(Object::String(text)) => text,
+        _ => return Object::error("json_parse expects a string"),
+    };
+    let mut structs = FxHashMap::default();
+    if let Some(Object::List(defs)) = args.get(1) {
+        for def in defs {
+            if let Object::StructDef { name, .. } = def {
+                structs.insert(name.to_string(), def.clone());
+            }
+        }
+    }
+    json::parse(text, &structs).unwrap_or_else(Object::error)
+}
+
+// Default tolerance of `approx`
+const APPROX_EPS: f64 = 1e-9;
+
+pub fn native_deep_equal(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let a = args.first().unwrap_or(&Object::Null);
+    let b = args.get(1).unwrap_or(&Object::Null);
+    Object::Bool(a.deep_equal(b, 0.0))
+}
+
+pub fn native_approx(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let a = args.first().unwrap_or(&Object::Null);
+    let b = args.get(1).unwrap_or(&Object::Null);
+    let eps = match args.get(2) {
+        Some(Object::Number(eps)) => eps.abs(),
+        _ => APPROX_EPS,
+    };
+    Object::Bool(a.deep_equal(b, eps))
+}
+
+// Applies `f` to a single number argument, anything else is `Object::Invalid`
+fn unary_math(args: &[Object], f: fn(f64) -> f64) -> Object {
+    match args {
+        [Object::Number(n)] => Object::Number(f(*n)),
+        _ => Object::Invalid,
+    }
+}
+
+pub fn math_sqrt(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    unary_math(&args, f64::sqrt)
+}
+
+pub fn math_abs(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    unary_math(&args, f64::abs)
+}
+
+pub fn math_floor(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    unary_math(&args, f64::floor)
+}
+
+pub fn math_ceil(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    unary_math(&args, f64::ceil)
+}
+
+pub fn math_round(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    unary_math(&args, f64::round)
+}
+
+pub fn math_sin(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    unary_math(&args, f64::sin)
+}
+
+pub fn math_cos(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    unary_math(&args, f64::cos)
+}
+
+// `/` rounded down, so `-7 / 2` gives -4 like Python's `//`
+pub fn math_floor_div(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match args.as_slice() {
+        [Object::Number(l), Object::Number(r)] if *r == 0.0 => {
+            Object::error(format!("Division by zero in math.floor_div({l}, {r})"))
+        }
+        [Object::Number(l), Object::Number(r)] => Object::Number((l / r).floor()),
+        _ => Object::Invalid,
+    }
+}
+
+// A whole number that isn't negative, for the combinatorics functions
+fn count_arg(obj: &Object) -> Option<f64> {
+    match obj {
+        Object::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n),
+        _ => None,
+    }
+}
+
+// Running product of the combinatorics functions, stays exact past 2^53
+// with the `bignum` feature
+fn times(acc: Object, n: f64) -> Object {
+    #[cfg(feature = "bignum")]
+    if let Some(product) =
+        crate::bigint::bin_op(&acc, &crate::lexer::TokenType::Multiply, &Object::Number(n))
+    {
+        return product;
+    }
+    match acc {
+        Object::Number(acc) => Object::Number(acc * n),
+        other => other,
+    }
+}
+
+// `acc / n` where n is known to divide acc
+fn divide_exact(acc: Object, n: f64) -> Object {
+    match acc {
+        Object::Number(acc) => Object::Number(acc / n),
+        #[cfg(feature = "bignum")]
+        Object::BigInt(acc) => acc.div_small(n as u64).into_object(),
+        other => other,
+    }
+}
+
+// `n * (n - 1) * .. * (n - k + 1)`, multiplied one factor at a time
+fn falling_product(n: f64, k: f64) -> Object {
+    let mut acc = Object::Number(1.0);
+    let mut i = 0.0;
+    while i < k {
+        acc = times(acc, n - i);
+        // Past f64::MAX the rest won't change anything
+        if matches!(acc, Object::Number(f) if f.is_infinite()) {
+            break;
+        }
+        i += 1.0;
+    }
+    acc
+}
+
+pub fn math_factorial(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match args.as_slice() {
+        [n] => count_arg(n).map_or(Object::Invalid, |n| falling_product(n, n)),
+        _ => Object::Invalid,
+    }
+}
+
+// Ordered picks, n! / (n - k)!
+pub fn math_perm(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match args.as_slice() {
+        [n, k] => match (count_arg(n), count_arg(k)) {
+            (Some(n), Some(k)) if k > n => Object::Number(0.0),
+            (Some(n), Some(k)) => falling_product(n, k),
+            _ => Object::Invalid,
+        },
+        _ => Object::Invalid,
+    }
+}
+
+// Binomial coefficient, n! / (k! * (n - k)!). After step i the product is
+// comb(n - k + i, i), so every division is exact
+pub fn math_comb(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let (n, k) = match args.as_slice() {
+        [n, k] => match (count_arg(n), count_arg(k)) {
+            (Some(n), Some(k)) => (n, k),
+            _ => return Object::Invalid,
+        },
+        _ => return Object::Invalid,
+    };
+    if k > n {
+        return Object::Number(0.0);
+    }
+    let k = k.min(n - k);
+    let mut acc = Object::Number(1.0);
+    let mut i = 1.0;
+    while i <= k {
+        acc = divide_exact(times(acc, n - k + i), i);
+        if matches!(acc, Object::Number(f) if f.is_infinite()) {
+            break;
+        }
+        i += 1.0;
+    }
+    acc
+}
+
+// A whole number of at least 1 that an f64 holds exactly
+fn positive_int_arg(args: &[Object]) -> Option<u64> {
+    match args {
+        [Object::Number(n)] if *n >= 1.0 && n.fract() == 0.0 && *n <= 9_007_199_254_740_992.0 => {
+            Some(*n as u64)
+        }
+        _ => None,
+    }
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result = 1;
+    base %= m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+// Miller-Rabin, the first 12 primes as witnesses make it exact for every u64
+fn is_prime(n: u64) -> bool {
+    const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    for p in WITNESSES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+    let shift = (n - 1).trailing_zeros();
+    let odd = (n - 1) >> shift;
+    WITNESSES.iter().all(|&a| {
+        let mut x = pow_mod(a, odd, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..shift {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+pub fn native_is_prime(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    positive_int_arg(&args).map_or(Object::Invalid, |n| Object::Bool(is_prime(n)))
+}
+
+// Prime factors in ascending order with repeats, `factorize(12)` is [2, 2, 3].
+// Trial division stops as soon as what's left is prime
+pub fn native_factorize(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let Some(mut n) = positive_int_arg(&args) else {
+        return Object::Invalid;
+    };
+    let mut factors = vec![];
+    let mut p = 2;
+    let mut done = is_prime(n);
+    while !done && p * p <= n {
+        if n.is_multiple_of(p) {
+            while n.is_multiple_of(p) {
+                factors.push(Object::Number(p as f64));
+                n /= p;
+            }
+            done = n == 1 || is_prime(n);
+        }
+        p += if p == 2 { 1 } else { 2 };
+    }
+    if n > 1 {
+        factors.push(Object::Number(n as f64));
+    }
+    Object::List(factors)
+}
+
+pub fn math_pow(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match args.as_slice() {
+        [Object::Number(n), Object::Number(exp)] => Object::Number(n.powf(*exp)),
+        _ => Object::Invalid,
+    }
+}
+
+// The `math` namespace in the base scope, `math.sqrt(2)`
+pub fn math_namespace() -> Object {
+    let functions: [(&str, NativeFn); 12] = [
+        ("sqrt", math_sqrt),
+        ("pow", math_pow),
+        ("floor_div", math_floor_div),
+        ("factorial", math_factorial),
+        ("comb", math_comb),
+        ("perm", math_perm),
+        ("abs", math_abs),
+        ("floor", math_floor),
+        ("ceil", math_ceil),
+        ("round", math_round),
+        ("sin", math_sin),
+        ("cos", math_cos),
+    ];
+    let mut math = native_functions(&functions);
+    math.insert("pi".to_string(), Object::Number(std::f64::consts::PI));
+    math.insert("e".to_string(), Object::Number(std::f64::consts::E));
+
+    Object::NameSpace {
+        name: "math".to_string(),
+        namespace: Box::new(math),
+    }
+}
+
+// Rows of a list of lists of numbers that all have the same length
+fn to_matrix(obj: &Object) -> Option<Vec<Vec<f64>>> {
+    let Object::List(rows) = obj else {
+        return None;
+    };
+    let matrix: Vec<Vec<f64>> = rows
+        .iter()
+        .map(|row| match row {
+            Object::List(row) => row
+                .iter()
+                .map(|item| match item {
+                    Object::Number(n) => Some(*n),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    let width = matrix.first().map_or(0, Vec::len);
+    matrix
+        .iter()
+        .all(|row| row.len() == width)
+        .then_some(matrix)
+}
+
+fn from_matrix(matrix: Vec<Vec<f64>>) -> Object {
+    Object::List(
+        matrix
+            .into_iter()
+            .map(|row| Object::List(row.into_iter().map(Object::Number).collect()))
+            .collect(),
+    )
+}
+
+// Matrix product, the columns of `a` have to match the rows of `b`
+pub fn matrix_multiply(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let (a, b) = match args.as_slice() {
+        [a, b] => match (to_matrix(a), to_matrix(b)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Object::Invalid,
+        },
+        _ => return Object::Invalid,
+    };
+    if a.first().map_or(0, Vec::len) != b.len() {
+        return Object::Invalid;
+    }
+    let columns = b.first().map_or(0, Vec::len);
+    let product = a
+        .iter()
+        .map(|row| {
+            (0..columns)
+                .map(|j| row.iter().zip(&b).map(|(x, b_row)| x * b_row[j]).sum())
+                .collect()
+        })
+        .collect();
+    from_matrix(product)
+}
+
+pub fn matrix_transpose(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let Some(m) = args.first().and_then(to_matrix) else {
+        return Object::Invalid;
+    };
+    let columns = m.first().map_or(0, Vec::len);
+    from_matrix(
+        (0..columns)
+            .map(|j| m.iter().map(|row| row[j]).collect())
+            .collect(),
+    )
+}
+
+// Largest `matrix.identity` size, a million cells. Past it a typo like
+// `identity(10000000000)` would exhaust memory and abort instead of failing
+const MAX_IDENTITY_SIZE: f64 = 1024.0;
+
+pub fn matrix_identity(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let n = match args.as_slice() {
+        [Object::Number(n)] if (0.0..=MAX_IDENTITY_SIZE).contains(n) && n.fract() == 0.0 => {
+            *n as usize
+        }
+        _ => return Object::Invalid,
+    };
+    from_matrix(
+        (0..n)
+            .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+            .collect(),
+    )
+}
+
+// Gaussian elimination, swapping in the row with the largest pivot each step
+// to keep the rounding small
+pub fn matrix_determinant(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let mut m = match args.first().and_then(to_matrix) {
+        Some(m) if !m.is_empty() && m.iter().all(|row| row.len() == m.len()) => m,
+        _ => return Object::Invalid,
+    };
+    let n = m.len();
+    let mut det = 1.0;
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&i, &j| m[i][col].abs().total_cmp(&m[j][col].abs()))
+            .unwrap_or(col);
+        if m[pivot][col] == 0.0 {
+            return Object::Number(0.0);
+        }
+        if pivot != col {
+            m.swap(pivot, col);
+            det = -det;
+        }
+        det *= m[col][col];
+        let (above, below) = m.split_at_mut(col + 1);
+        let pivot_row = &above[col];
+        for row in below {
+            let factor = row[col] / pivot_row[col];
+            for (x, p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *x -= factor * p;
+            }
+        }
+    }
+    Object::Number(det)
+}
+
+// The `matrix` namespace, matrices are lists of rows like `[[1, 2], [3, 4]]`
+pub fn matrix_namespace() -> Object {
+    let functions: [(&str, NativeFn); 4] = [
+        ("multiply", matrix_multiply),
+        ("transpose", matrix_transpose),
+        ("identity", matrix_identity),
+        ("determinant", matrix_determinant),
+    ];
+    Object::NameSpace {
+        name: "matrix".to_string(),
+        namespace: Box::new(native_functions(&functions)),
+    }
+}
+
+fn native_functions(functions: &[(&str, NativeFn)]) -> FxHashMap<String, Object> {
+    functions
+        .iter()
+        .map(|&(name, function)| {
+            let native = Object::NativeFn {
+                name: name.to_string(),
+                function,
+            };
+            (name.to_string(), native)
+        })
+        .collect()
+}
+
+// Levels from the least to the most severe, messages below the level set with
+// `log.set_level` are dropped
+pub const LOG_LEVELS: [&str; 4] = ["debug", "info", "warn", "error"];
+
+pub fn log_namespace() -> Object {
+    let functions: [(&str, NativeFn); 6] = [
+        ("debug", log_debug),
+        ("info", log_info),
+        ("warn", log_warn),
+        ("error", log_error),
+        ("set_level", log_set_level),
+        ("level", log_level),
+    ];
+    Object::NameSpace {
+        name: "log".to_string(),
+        namespace: Box::new(native_functions(&functions)),
+    }
+}
+
+// Writes `2024-01-31 12:00:00 [WARN] args..` to stderr, args are separated by
+// spaces like `write` would print them
+fn log_message(level: usize, args: &[Object], vm: &Interpreter) -> Object {
+    if level < vm.log_level {
+        return Object::Null;
+    }
+    let (year, month, day, hour, minute, second) = civil_from_timestamp(now_timestamp());
+    let message: Vec<String> = args.iter().map(Object::to_string).collect();
+    eprintln!(
+        "{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02} [{}] {}",
+        LOG_LEVELS[level].to_uppercase(),
+        message.join(" ")
+    );
+    Object::Null
+}
+
+pub fn log_debug(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    log_message(0, &args, vm)
+}
+
+pub fn log_info(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    log_message(1, &args, vm)
+}
+
+pub fn log_warn(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    log_message(2, &args, vm)
+}
+
+pub fn log_error(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    log_message(3, &args, vm)
+}
+
+pub fn log_set_level(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    let level = match args.first() {
+        Some(Object::String(name)) => LOG_LEVELS.iter().position(|level| **level == **name),
+        _ => None,
+    };
+    match level {
+        Some(level) => {
+            vm.log_level = level;
+            Object::Null
+        }
+        None => Object::error(format!(
+            "log.set_level expects one of {}",
+            LOG_LEVELS.join(", ")
+        )),
+    }
+}
+
+pub fn log_level(_: Vec<Object>, vm: &mut Interpreter) -> Object {
+    Object::String(Box::new(LOG_LEVELS[vm.log_level].to_string()))
+}
+
+pub fn native_random(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    match args.as_slice() {
+        [] => Object::Number(vm.random_f64()),
+        [Object::Number(a), Object::Number(b)] => Object::Number(a + (b - a) * vm.random_f64()),
+        _ => Object::error("random expects no args or two numbers"),
+    }
+}
+
+pub fn native_random_int(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    let (min, max) = match (
+        args.first().and_then(Object::get_int_value),
+        args.get(1).and_then(Object::get_int_value),
+    ) {
+        (Some(min), Some(max)) if min <= max => (min, max),
+        _ => return Object::error("random_int expects two integers with min <= max"),
+    };
+    // The span can be up to 2^64 so it is computed in i128
+    let span = (max as i128 - min as i128 + 1) as u128;
+    let offset = (vm.random_u64() as u128 % span) as i128;
+    Object::Number((min as i128 + offset) as f64)
+}
+
+// Copies of a frozen value, e.g. `let copy = frozen`, are not frozen
+pub fn native_freeze(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match args.into_iter().next() {
+        Some(obj @ (Object::List(_) | Object::Map(_) | Object::Instance { .. })) => {
+            Object::Frozen(Box::new(obj))
+        }
+        Some(obj) => obj,
+        None => Object::Null,
+    }
+}
+
+pub fn native_clock(_: Vec<Object>, vm: &mut Interpreter) -> Object {
+    Object::Number(vm.elapsed_secs())
+}
+
+pub fn native_sleep(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match args.first() {
+        Some(Object::Number(secs)) => match Duration::try_from_secs_f64(secs.max(0.0)) {
+            Ok(duration) => thread::sleep(duration),
+            Err(_) => return Object::error(format!("Can't sleep for {secs} seconds")),
+        },
+        _ => return Object::error("sleep expects a number of seconds"),
+    }
+    Object::Null
+}
+
+// Strings that don't parse give `Object::Invalid` like everywhere else numbers
+// are coerced, use `try_number` to branch on the failure instead
+pub fn native_to_number(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    args.first().unwrap_or(&Object::Null).to_number_obj()
+}
+
+pub fn native_to_string(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    Object::String(Box::new(args.first().unwrap_or(&Object::Null).to_string()))
+}
+
+pub fn native_ref(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    Object::Ref(Shared::new(args.into_iter().next().unwrap_or(Object::Null)))
+}
+
+pub fn native_deref(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    match args.first() {
+        Some(Object::Ref(cell)) => cell.get(),
+        _ => Object::error("deref expects a ref"),
+    }
+}
+
+pub fn native_set(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(Object::Ref(cell)), value) => {
+            cell.set(value.unwrap_or(Object::Null));
+            Object::Null
+        }
+        _ => Object::error("set expects a ref and a value"),
+    }
+}
+
+// Map from a list of `[key, value]` pairs, later pairs win on repeated keys.
+// Keys that aren't strings are stored by their string form like indexing does
+pub fn native_from_entries(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let Some(Object::List(pairs)) = args.into_iter().next().map(Object::unfrozen) else {
+        return Object::error("from_entries expects a list of [key, value] pairs");
+    };
+    let mut map = FxHashMap::default();
+    for pair in pairs {
+        match pair.unfrozen() {
+            Object::List(pair) if pair.len() == 2 => {
+                let mut pair = pair.into_iter();
+                let key = pair.next().unwrap_or_default().get_string_value();
+                map.insert(key, pair.next().unwrap_or_default());
+            }
+            other => {
+                return Object::error(format!("from_entries expects [key, value] found {other}"))
+            }
+        }
+    }
+    Object::Map(map)
+}
+
+pub fn native_heap(_: Vec<Object>, _: &mut Interpreter) -> Object {
+    Object::Heap(Box::default())
+}
+
+fn sorted_names(names: impl Iterator<Item = String>) -> Object {
+    let mut names: Vec<String> = names.collect();
+    names.sort();
+    Object::List(
+        names
+            .into_iter()
+            .map(|name| Object::String(Box::new(name)))
+            .collect(),
+    )
+}
+
+pub fn native_describe(args: Vec<Object>, _: &mut Interpreter) -> Object {
+    let obj = args.first().unwrap_or(&Object::Null);
+    let mut description = FxHashMap::default();
+    description.insert(
+        "type".to_string(),
+        Object::String(Box::new(obj.type_name().to_string())),
+    );
+    description.insert(
+        "value".to_string(),
+        Object::String(Box::new(obj.to_string())),
+    );
+
+    match obj {
+        Object::String(_) | Object::List(_) => {
+            description.insert("len".to_string(), Object::Number(obj.get_len() as f64));
+        }
+        Object::Map(map) => {
+            description.insert("len".to_string(), Object::Number(map.len() as f64));
+            description.insert("keys".to_string(), sorted_names(map.keys().cloned()));
+        }
+        Object::Instance { fields, .. } => {
+            description.insert("fields".to_string(), sorted_names(fields.keys().cloned()));
+        }
+        Object::StructDef {
+            fields, methods, ..
+        } => {
+            description.insert("fields".to_string(), sorted_names(fields.keys().cloned()));
+            description.insert("methods".to_string(), sorted_names(methods.keys().cloned()));
+        }
+        Object::NameSpace { namespace, .. } => {
+            description.insert(
+                "fields".to_string(),
+                sorted_names(namespace.keys().cloned()),
+            );
+        }
+        Object::Fn { args, rest, .. } => {
+            let params = args
+                .iter()
+                .map(|(name, _)| name.clone())
+                .chain(rest.iter().map(|rest| format!("...{rest}")))
+                .map(|name| Object::String(Box::new(name)));
+            description.insert("params".to_string(), Object::List(params.collect()));
+        }
+        _ => {}
+    }
+    Object::Map(description)
+}
+
+// The object count is only an estimate, every value counts as one object
+// no matter how large its string or body is
+pub fn native_mem_report(_: Vec<Object>, vm: &mut Interpreter) -> Object {
+    let (scope_depth, variables, objects) = vm.mem_usage();
+    let mut report = FxHashMap::default();
+    report.insert(
+        "scope_depth".to_string(),
+        Object::Number(scope_depth as f64),
+    );
+    report.insert("variables".to_string(), Object::Number(variables as f64));
+    report.insert("objects".to_string(), Object::Number(objects as f64));
+    Object::Map(report)
+}
+
 pub fn native_exit(args: Vec<Object>, _: &mut Interpreter) -> Object {
-    if let Some(Object::Number(code)) = args.get(0) {
-        std::process::exit(*code as i32);
+    if let Some(code) = args.first().and_then(Object::get_int_value) {
+        std::process::exit(i32::try_from(code).unwrap_or(-1));
     } else {
         std::process::exit(-1);
     }
 }
 
 pub fn get_var_from_str(args: Vec<Object>, vm: &mut Interpreter) -> Object {
-    if let Some(Object::String(name)) = args.get(0) {
+    if let Some(Object::String(name)) = args.first() {
         return vm.get_var(name).unwrap_or(&mut Object::Null).clone();
     }
     Object::Null
 }
+
+pub fn native_help(args: Vec<Object>, vm: &mut Interpreter) -> Object {
+    let name = match args.first() {
+        Some(Object::String(name)) => name.to_string(),
+        _ => {
+            let mut names: Vec<&str> = HELP.keys().copied().collect();
+            names.sort();
+            println!("Builtins: {}", names.join(", "));
+            return Object::Null;
+        }
+    };
+
+    if let Some((signature, description)) = HELP.get(name.as_str()) {
+        println!("{signature}\n\t{description}");
+    } else if let Some(Object::Fn { args, rest, .. }) = vm.get_var(&name) {
+        let params: Vec<String> = args
+            .iter()
+            .map(|(arg, default)| match default {
+                Object::Null => arg.to_string(),
+                default => format!("{arg} = {default}"),
+            })
+            .chain(rest.iter().map(|rest| format!("...{rest}")))
+            .collect();
+        println!("fn {name}({})", params.join(", "));
+    } else {
+        println!("No help for {name}");
+    }
+    Object::Null
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(src: &str) -> Object {
+        Interpreter::new(".".to_string(), None).eval_str(src)
+    }
+
+    #[test]
+    fn is_prime_and_factorize_whole_numbers() {
+        assert_eq!(eval("is_prime(1)"), Object::Bool(false));
+        assert_eq!(eval("is_prime(2)"), Object::Bool(true));
+        assert_eq!(eval("is_prime(97)"), Object::Bool(true));
+        assert_eq!(eval("is_prime(91)"), Object::Bool(false));
+        assert_eq!(eval("factorize(360)").to_string(), "[2, 2, 2, 3, 3, 5]");
+        assert_eq!(eval("factorize(97)").to_string(), "[97]");
+        assert_eq!(eval("factorize(1)").to_string(), "[]");
+        assert_eq!(eval("is_prime(0)"), Object::Invalid);
+        assert_eq!(eval("factorize(2.5)"), Object::Invalid);
+    }
+
+    #[test]
+    fn currency_and_percent_group_and_round() {
+        let text = |s: &str| Object::String(Box::new(s.to_string()));
+        assert_eq!(eval("currency(1234567.891, \"$\")"), text("$1,234,567.89"));
+        assert_eq!(eval("currency(-1234.5)"), text("-1,234.50"));
+        assert_eq!(eval("percent(0.125)"), text("12.50%"));
+        assert_eq!(eval("currency(\"x\")"), Object::Null);
+    }
+
+    #[test]
+    fn format_date_fills_utc_fields() {
+        let text = |s: &str| Object::String(Box::new(s.to_string()));
+        assert_eq!(eval("format_date(0)"), text("1970-01-01 00:00:00"));
+        // A leap day, `%%` is a percent sign and unknown specifiers stay
+        assert_eq!(
+            eval("format_date(951782400, \"%d/%m/%Y %% %q\")"),
+            text("29/02/2000 % %q")
+        );
+        assert_eq!(
+            eval("format_date(1700000000, \"%H:%M:%S\")"),
+            text("22:13:20")
+        );
+        assert_eq!(eval("format_date(\"now\")"), Object::Null);
+        let Object::Map(now) = eval("date_now()") else {
+            panic!("expected a map");
+        };
+        assert!(matches!(now.get("month"), Some(Object::Number(m)) if (1.0..=12.0).contains(m)));
+    }
+
+    #[test]
+    fn identity_rejects_sizes_past_the_cap() {
+        assert_eq!(eval("matrix.identity(10000000000)"), Object::Invalid);
+        assert_eq!(eval("matrix.identity(1025)"), Object::Invalid);
+        assert_eq!(eval("matrix.identity(-1)"), Object::Invalid);
+        assert_eq!(eval("matrix.identity(2)").to_string(), "[[1, 0], [0, 1]]");
+    }
+
+    #[test]
+    fn format_fills_placeholders_and_unescapes_braces() {
+        let text = |s: &str| Object::String(Box::new(s.to_string()));
+        assert_eq!(eval("format(\"{} + {} = {}\", 1, 2, 3)"), text("1 + 2 = 3"));
+        assert_eq!(
+            eval("let x = 5\nformat(\"{{x}} is {}\", x)"),
+            text("{x} is 5")
+        );
+        assert_eq!(eval("let x = 5\nformat(\"{{{x}}}\")"), text("{5}"));
+    }
+
+    #[test]
+    fn format_rejects_bad_templates() {
+        for src in [
+            "format(\"{} {}\", 1)",
+            "format(\"{}\", 1, 2)",
+            "format(\"a } b\")",
+            "format(1)",
+            "format()",
+        ] {
+            assert!(matches!(eval(src), Object::Error { .. }), "{src}");
+        }
+    }
+
+    #[test]
+    fn run_timers_returns_callback_errors() {
+        let src = "let hits = 0\nafter(0, fn() => log.set_level(\"bogus\"))\nafter(0, fn() => { hits += 1 })\nlet r = try { run_timers() } catch e { \"caught\" }\n";
+        assert_eq!(
+            eval(&format!("{src}r")),
+            Object::String(Box::new("caught".to_string()))
+        );
+        // The timer after the failing one is still pending and runs next time
+        assert_eq!(eval(&format!("{src}hits")), Object::Number(0.0));
+        assert_eq!(
+            eval(&format!("{src}run_timers()\nhits")),
+            Object::Number(1.0)
+        );
+    }
+
+    #[test]
+    fn after_and_file_natives_return_catchable_errors() {
+        let caught = |call: &str| {
+            eval(&format!(
+                "let r = try {{ {call} }} catch e {{ \"caught\" }}\nr"
+            )) == Object::String(Box::new("caught".to_string()))
+        };
+        assert!(caught("after(\"x\", 5)"));
+        assert!(caught("read_file(\"/no/such/dir/file.txt\")"));
+        assert!(caught("read_file(1)"));
+        assert!(caught("write_file(\"/no/such/dir/file.txt\", \"x\")"));
+        assert!(caught("write_file(1)"));
+        assert_eq!(eval("after(0, fn() => 1)"), Object::Null);
+    }
+}